@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+/// Files of a new site, relative to its directory
+const FILES: &[(&str, &str)] = &[
+    (
+        "puggle.yml",
+        r#"templates_dir: templates
+dest_dir: dist
+hashed_assets_dir: static
+
+pages:
+  - name: blog
+    template_path: layout/blog.html
+
+    entries:
+      - source_dir: blog/posts
+        template_path: layout/post.html
+"#,
+    ),
+    (
+        "templates/layout/base.html",
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>{% block title %}My site{% endblock %}</title>
+    <link rel="stylesheet" href="{{ "css/style.css"|asset_url }}">
+  </head>
+
+  <body>
+    {% block body %}{% endblock %}
+  </body>
+</html>
+"#,
+    ),
+    (
+        "templates/layout/blog.html",
+        r#"{% extends "layout/base.html" %}
+
+{% block title %}Blog{% endblock %}
+
+{% block body %}
+  <h1>Blog</h1>
+
+  <ul>
+    {% for entry in pages.blog %}
+      <li><a href="{{ entry.url }}">{{ entry.title }}</a></li>
+    {% endfor %}
+  </ul>
+{% endblock %}
+"#,
+    ),
+    (
+        "templates/layout/post.html",
+        r#"{% extends "layout/base.html" %}
+
+{% block title %}{{ metadata.title }}{% endblock %}
+
+{% block body %}
+  <article>
+    {% block content %}{% endblock %}
+  </article>
+{% endblock %}
+"#,
+    ),
+    (
+        "static/css/style.css",
+        r#"body {
+  max-width: 40rem;
+  margin: 0 auto;
+  padding: 1rem;
+  font-family: sans-serif;
+}
+"#,
+    ),
+    (
+        "blog/posts/hello-world.md",
+        r#"---
+title: Hello, world!
+summary: My first post
+created_at: 2024-06-29T17:29:00Z
+updated_at:
+tags: ["hello"]
+---
+
+# {{ metadata.title }}
+
+Welcome to my new site. Edit `blog/posts/hello-world.md`, then run
+`puggle build` to see your changes in `dist`.
+"#,
+    ),
+];
+
+/// Files of a new site in `dir` that already exist
+pub fn existing_files(dir: &Path) -> Vec<PathBuf> {
+    FILES
+        .iter()
+        .map(|(path, _)| *path)
+        // The other name puggle reads its config from
+        .chain(std::iter::once("puggle.yaml"))
+        .map(|path| dir.join(path))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Writes a minimal site to `dir`. Files that already exist are overwritten,
+/// so check for them with `existing_files` first.
+pub fn scaffold(dir: &Path) -> std::io::Result<()> {
+    for (path, contents) in FILES {
+        let path = dir.join(path);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
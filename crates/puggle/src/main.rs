@@ -1,3 +1,7 @@
+mod init;
+
+use std::path::PathBuf;
+
 pub use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
@@ -5,26 +9,352 @@ pub use clap::{Parser, Subcommand};
 pub struct Args {
     #[command(subcommand)]
     pub command: Command,
+    /// Reads the config from this file, or from every YAML and TOML file in
+    /// this directory, instead of puggle.yml
+    #[arg(long, global = true, alias = "config-path", value_name = "PATH")]
+    pub config: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 pub enum Command {
     /// Runs the server
+    #[cfg(feature = "server")]
+    Server {
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+        /// Overrides the port the server listens on. 0 picks a free one.
+        #[arg(long)]
+        port: Option<u16>,
+        #[command(flatten)]
+        tls: TlsArgs,
+    },
+    /// Runs the server (unavailable: built without the `server` feature)
+    #[cfg(not(feature = "server"))]
+    #[command(hide = true)]
     Server,
     /// Generates blog markdown files into full pages
-    Build,
+    Build {
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+        /// Writes a manifest.json of every generated file to the dest dir
+        #[arg(long)]
+        manifest: bool,
+        /// Renders entries marked as drafts
+        #[arg(long)]
+        drafts: bool,
+        /// Renders entries created in the future
+        #[arg(long)]
+        future: bool,
+        /// Lists the files a build would write without writing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Only builds the page with this name. Can be repeated.
+        #[arg(long = "page", value_name = "NAME")]
+        pages: Vec<String>,
+        /// Exits with an error if the build had any warnings
+        #[arg(long, alias = "deny-warnings")]
+        strict: bool,
+        /// Builds every entry it can, and reports the ones that failed at the
+        /// end
+        #[arg(long)]
+        keep_going: bool,
+        /// Times each phase of the build, and prints how long they took
+        #[arg(long)]
+        profile: bool,
+        /// Runs the config's `post_build` command once the build succeeds
+        #[arg(long, conflicts_with = "dry_run")]
+        run_hooks: bool,
+        /// Runs the server once the build succeeds
+        #[arg(long, conflicts_with = "dry_run")]
+        serve: bool,
+        /// Overrides the port the server listens on with --serve. 0 picks a
+        /// free one.
+        #[arg(long, requires = "serve")]
+        port: Option<u16>,
+        /// How to print the build report
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+    /// Creates a minimal site to start from
+    Init {
+        /// Directory to create the site in
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Checks the config for problems without building anything
+    Check {
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+}
+
+/// Shape of what `build` prints to stdout. Warnings and errors always go to
+/// stderr.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Lists the files written by a dry run
+    Human,
+    /// Prints the whole build report as JSON
+    Json,
+}
+
+/// Replaces values from the config file for a single invocation
+#[derive(clap::Args)]
+pub struct ConfigOverrides {
+    /// Overrides the config's `dest_dir`
+    #[arg(long)]
+    pub dest_dir: Option<PathBuf>,
+    /// Overrides the config's `templates_dir`
+    #[arg(long)]
+    pub templates_dir: Option<PathBuf>,
+}
+
+impl ConfigOverrides {
+    fn apply(self, config: &mut puggle_lib::Config) -> std::io::Result<()> {
+        if let Some(dest_dir) = self.dest_dir {
+            config.dest_dir = std::path::absolute(dest_dir)?;
+        }
+
+        if let Some(templates_dir) = self.templates_dir {
+            config.templates_dir = std::path::absolute(templates_dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Serves over HTTPS with a certificate of your own, e.g. one made with
+/// `mkcert` for local testing
+#[cfg(feature = "server")]
+#[derive(clap::Args)]
+pub struct TlsArgs {
+    /// Serves over HTTPS (needs the `tls` feature)
+    #[arg(long, requires_all = ["cert", "key"])]
+    pub tls: bool,
+    /// PEM encoded certificate to serve with
+    #[arg(long, requires = "tls")]
+    pub cert: Option<PathBuf>,
+    /// PEM encoded private key of the certificate
+    #[arg(long, requires = "tls")]
+    pub key: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() {
+fn main() {
     let cli = Args::parse();
     color_eyre::install().unwrap();
-    let config = puggle_lib::Config::from_file().unwrap();
+    // Not every command needs a config, e.g. `init`
+    let config = || puggle_lib::Config::from_path(cli.config.as_deref()).unwrap();
 
     match cli.command {
-        Command::Server => puggle_server::run(config).await.unwrap(),
-        Command::Build {} => puggle_lib::build_from_dir(config)
-            .inspect_err(|e| println!("{:?}", e))
-            .unwrap(),
+        #[cfg(feature = "server")]
+        Command::Server {
+            overrides,
+            port,
+            tls,
+        } => {
+            let mut config = config();
+            overrides.apply(&mut config).unwrap();
+            config.port = port.unwrap_or(config.port);
+
+            match (tls.tls, tls.cert, tls.key) {
+                (true, Some(cert), Some(key)) => serve_tls(config, cert, key),
+                _ => serve(config),
+            }
+        }
+        #[cfg(not(feature = "server"))]
+        Command::Server => server_unavailable(),
+        Command::Build {
+            overrides,
+            manifest,
+            drafts,
+            future,
+            dry_run,
+            pages,
+            strict,
+            keep_going,
+            profile,
+            run_hooks,
+            serve: and_serve,
+            port,
+            format,
+        } => {
+            let mut config = config();
+            overrides.apply(&mut config).unwrap();
+            config.port = port.unwrap_or(config.port);
+            config.manifest |= manifest;
+            config.drafts |= drafts;
+            config.future |= future;
+            config.dry_run |= dry_run;
+            config.keep_going |= keep_going;
+            config.profile |= profile;
+            config.only_pages.extend(pages);
+            config.env.get_or_insert(if and_serve {
+                puggle_lib::SiteEnv::Dev
+            } else {
+                puggle_lib::SiteEnv::Prod
+            });
+
+            let report = puggle_lib::build_from_dir(config.clone())
+                .inspect_err(|e| eprintln!("{:?}", e))
+                .unwrap();
+
+            match format {
+                OutputFormat::Human if dry_run => {
+                    for file in report.files.iter() {
+                        println!("{:>9} {}", file.change, file.path.display());
+                    }
+                }
+                OutputFormat::Human => {}
+                OutputFormat::Json => println!("{}", serde_json::to_string(&report).unwrap()),
+            }
+
+            if let (OutputFormat::Human, Some(profile)) = (format, report.profile.as_ref()) {
+                for (phase, duration) in profile.phases() {
+                    eprintln!("{:>6} {:>10.2}ms", phase, duration.as_secs_f64() * 1000.0);
+                }
+            }
+
+            for warning in report.warnings.iter() {
+                eprintln!("warning: {}", warning);
+            }
+
+            for error in report.errors.iter() {
+                eprintln!(
+                    "error: failed to build {:?}. reason: {}",
+                    error.path, error.message
+                );
+            }
+
+            if !report.errors.is_empty() {
+                eprintln!(
+                    "error: {} entry file(s) failed to build",
+                    report.errors.len()
+                );
+                std::process::exit(1);
+            }
+
+            if strict && !report.warnings.is_empty() {
+                eprintln!(
+                    "error: the build had {} warning(s), which --strict denies",
+                    report.warnings.len()
+                );
+                std::process::exit(1);
+            }
+
+            if run_hooks {
+                run_post_build(&config);
+            }
+
+            if and_serve {
+                serve(config);
+            }
+        }
+        Command::Init { dir } => {
+            let existing = init::existing_files(dir.as_path());
+
+            if !existing.is_empty() {
+                for path in existing.iter() {
+                    eprintln!("error: {:?} already exists", path);
+                }
+
+                eprintln!("refusing to overwrite an existing site");
+                std::process::exit(1);
+            }
+
+            init::scaffold(dir.as_path()).unwrap();
+            println!(
+                "created a new site in {:?}. run `puggle build` to build it",
+                dir
+            );
+        }
+        Command::Check { overrides } => {
+            let mut config = config();
+            overrides.apply(&mut config).unwrap();
+
+            if let Err(issues) = config.validate() {
+                for issue in issues.iter() {
+                    eprintln!("error: {}", issue);
+                }
+
+                eprintln!("found {} issue(s) in the config", issues.len());
+                std::process::exit(1);
+            }
+
+            println!("no issues found in the config");
+        }
+    };
+}
+
+/// Runs the config's `post_build` command in a shell, with its output going
+/// straight to the terminal. Exits with the command's status if it fails.
+fn run_post_build(config: &puggle_lib::Config) {
+    let Some(ref command) = config.post_build else {
+        eprintln!("warning: --run-hooks was given, but there is no `post_build` command");
+        return;
+    };
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
     };
+
+    let status = shell
+        .arg(command)
+        .env(
+            "PUGGLE_DEST_DIR",
+            std::path::absolute(&config.dest_dir).unwrap(),
+        )
+        .status()
+        .unwrap();
+
+    if !status.success() {
+        eprintln!("error: post_build command failed with {}", status);
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+#[cfg(feature = "server")]
+fn serve(config: puggle_lib::Config) {
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(puggle_server::run(config))
+        .unwrap()
+}
+
+#[cfg(feature = "tls")]
+fn serve_tls(config: puggle_lib::Config, cert: PathBuf, key: PathBuf) {
+    let files = puggle_server::TlsFiles { cert, key };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    if let Err(e) = runtime.block_on(puggle_server::run_tls(config, files)) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(feature = "server", not(feature = "tls")))]
+fn serve_tls(_config: puggle_lib::Config, _cert: PathBuf, _key: PathBuf) {
+    eprintln!("error: --tls is unavailable because puggle was built without the `tls` feature");
+    std::process::exit(1);
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_config: puggle_lib::Config) {
+    server_unavailable()
+}
+
+/// Exits with an error, without needing a config, so that it's what users see
+/// even without one
+#[cfg(not(feature = "server"))]
+fn server_unavailable() -> ! {
+    eprintln!(
+        "error: the server is unavailable because puggle was built without the `server` \
+        feature"
+    );
+    std::process::exit(1);
 }
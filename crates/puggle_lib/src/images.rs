@@ -0,0 +1,159 @@
+#[cfg(feature = "images")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImagesConfig {
+    /// Widths, in pixels, of the variants generated for every image. Widths
+    /// larger than the image itself are skipped.
+    pub widths: Vec<u32>,
+    #[serde(default = "default_formats")]
+    pub formats: Vec<ImageFormat>,
+    /// Directory that site-absolute image paths, like `/assets/cover.jpg`,
+    /// are read from. Relative paths are read from the entry's directory.
+    #[serde(default = "default_source_dir")]
+    pub source_dir: PathBuf,
+    /// Resizes the local images in entries' content too, and gives them a
+    /// `srcset`
+    pub responsive: Option<ResponsiveConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResponsiveConfig {
+    /// Widths, in pixels, of the variants generated for every image in
+    /// entries' content
+    pub widths: Vec<u32>,
+    /// `sizes` of every resized image in entries' content
+    #[serde(default = "default_sizes")]
+    pub sizes: String,
+}
+
+fn default_sizes() -> String {
+    "100vw".to_string()
+}
+
+fn default_formats() -> Vec<ImageFormat> {
+    vec![ImageFormat::Webp]
+}
+
+fn default_source_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    /// Format of the image at `path`, going by its extension
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+
+        match extension.to_ascii_lowercase().as_str() {
+            "webp" => Some(ImageFormat::Webp),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// A resized copy of an image, in one of the configured formats
+#[derive(Debug)]
+pub struct ImageVariant {
+    /// File name of the variant, which is written next to where the
+    /// original image is served from
+    pub file_name: String,
+    pub width: u32,
+    pub format: ImageFormat,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("failed to read image {0:?}. reason: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[cfg(feature = "images")]
+    #[error("failed to process image {0:?}. reason: {1}")]
+    Process(PathBuf, image::ImageError),
+}
+
+/// Whether `src` points to an image puggle can't read, i.e. anything that
+/// isn't a path on the same site.
+pub fn is_remote(src: &str) -> bool {
+    src.starts_with("//") || src.contains("://") || src.starts_with("data:")
+}
+
+/// Generates a variant of the image at `source` for every one of `widths`
+/// and `formats`.
+#[cfg(feature = "images")]
+pub fn resize(
+    source: &Path,
+    widths: &[u32],
+    formats: &[ImageFormat],
+) -> Result<Vec<ImageVariant>, ImageError> {
+    use image::{imageops::FilterType, DynamicImage};
+
+    let contents = std::fs::read(source).map_err(|e| ImageError::Io(source.to_path_buf(), e))?;
+    let original = image::load_from_memory(contents.as_slice())
+        .map_err(|e| ImageError::Process(source.to_path_buf(), e))?;
+    let stem = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut variants = Vec::new();
+
+    for width in widths.iter().copied() {
+        if width == 0 || width > original.width() {
+            continue;
+        }
+
+        let resized = original.resize(width, u32::MAX, FilterType::Lanczos3);
+
+        for format in formats.iter().copied() {
+            // Not every encoder supports every color type
+            let (resized, image_format) = match format {
+                ImageFormat::Webp => (
+                    DynamicImage::ImageRgba8(resized.to_rgba8()),
+                    image::ImageFormat::WebP,
+                ),
+                ImageFormat::Jpeg => (
+                    DynamicImage::ImageRgb8(resized.to_rgb8()),
+                    image::ImageFormat::Jpeg,
+                ),
+                ImageFormat::Png => (resized.clone(), image::ImageFormat::Png),
+            };
+
+            let mut contents = std::io::Cursor::new(Vec::new());
+
+            resized
+                .write_to(&mut contents, image_format)
+                .map_err(|e| ImageError::Process(source.to_path_buf(), e))?;
+
+            variants.push(ImageVariant {
+                file_name: format!("{}-{}w.{}", stem, width, format.extension()),
+                width,
+                format,
+                contents: contents.into_inner(),
+            });
+        }
+    }
+
+    Ok(variants)
+}
@@ -1,20 +1,329 @@
+pub mod images;
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ffi::OsStr,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use minijinja::{value::Kwargs, Environment, State, Value};
-use pulldown_cmark::{Event, MetadataBlockKind, Parser, Tag, TagEnd};
+use pulldown_cmark::{
+    CodeBlockKind, CowStr, Event, HeadingLevel, MetadataBlockKind, Parser, Tag, TagEnd,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use time::OffsetDateTime;
 
-#[derive(Debug, Deserialize)]
+use crate::images::{ImageFormat, ImagesConfig};
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub pages: Vec<Page>,
     pub templates_dir: PathBuf,
     pub dest_dir: PathBuf,
+    /// Writes a `manifest.json` of every generated file to `dest_dir`
+    #[serde(default)]
+    pub manifest: bool,
+    /// Writes a `search-index.json` of every entry to `dest_dir`
+    #[serde(default)]
+    pub search_index: bool,
+    /// Renders entries marked as `draft` instead of skipping them
+    #[serde(default)]
+    pub drafts: bool,
+    /// Renders entries whose `created_at` is still to come instead of
+    /// skipping them
+    #[serde(default)]
+    pub future: bool,
+    /// Injects a banner at the top of every rendered draft
+    #[serde(default)]
+    pub draft_banner: bool,
+    /// Number of levels every markdown heading is shifted down by, e.g. an
+    /// offset of 1 turns an `h1` into an `h2`. Levels are capped at `h6`.
+    #[serde(default)]
+    pub heading_offset: u8,
+    /// Languages of fenced code blocks that are emitted as-is, wrapped in a
+    /// `<div>` with the language as its class, for client-side renderers such
+    /// as mermaid.
+    #[serde(default)]
+    pub passthrough_languages: Vec<String>,
+    /// Extensions of the files in a source directory that are rendered as
+    /// entries, matched case-insensitively. Other files are ignored.
+    #[serde(default = "default_markdown_extensions")]
+    pub markdown_extensions: Vec<String>,
+    /// Extensions of the files in a source directory that are entries written
+    /// in HTML rather than markdown. Everything after their front matter is
+    /// used as is.
+    #[serde(default)]
+    pub html_extensions: Vec<String>,
+    #[serde(default)]
+    pub url_trailing_slash: UrlTrailingSlash,
+    /// How aliases redirect to their entry
+    #[serde(default)]
+    pub redirect_style: RedirectStyle,
+    /// Headers hosts like Netlify and Cloudflare Pages send along with the
+    /// site's files, written to a `_headers` file at the root of `dest_dir`
+    #[serde(default)]
+    pub headers: Vec<HeaderRule>,
+    /// Environment the site is built for, available to templates as
+    /// `site.env`. When unset, `puggle build --serve` builds for `dev`, and
+    /// `puggle build` for `prod`.
+    pub env: Option<SiteEnv>,
+    /// Values available to every template by their name, e.g. the site's
+    /// navigation or social links
+    #[serde(default)]
+    pub globals: BTreeMap<String, serde_yml::Value>,
+    /// What templates do with variables that aren't defined
+    #[serde(default)]
+    pub undefined_behavior: UndefinedBehavior,
+    /// Path the site is served under, e.g. `/docs` for a site deployed to
+    /// `https://example.com/docs/`. Prepended to every URL puggle emits,
+    /// without changing where files are written.
+    pub base_path: Option<String>,
+    /// Directory that entries' `source_path` is relative to, e.g. the root of
+    /// the repository. Defaults to the working directory.
+    pub content_root: Option<PathBuf>,
+    /// Goes through the whole build without writing anything to `dest_dir`.
+    /// The build report still lists what would have been written.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Carries on with the rest of the build when an entry fails to build,
+    /// and lists it in the build report's `errors` instead
+    #[serde(default)]
+    pub keep_going: bool,
+    /// Times each phase of the build, and adds the timings to the build
+    /// report's `profile`
+    #[serde(default)]
+    pub profile: bool,
+    /// Shell command that `puggle build --run-hooks` runs after a successful
+    /// build, e.g. to deploy `dest_dir`
+    pub post_build: Option<String>,
+    /// Port `puggle server` listens on. With 0, the OS picks a free one.
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Strips trailing whitespace and runs of blank lines from generated
+    /// HTML, and ends every file with a single newline. Contents of `<pre>`
+    /// blocks are left alone.
+    #[serde(default)]
+    pub normalize_html: bool,
+    /// Adds the raw source of every code block to its `<pre>` as a
+    /// `data-code` attribute, for copy buttons.
+    #[serde(default)]
+    pub copy_code: bool,
+    /// Expands emoji shortcodes like `:tada:` in markdown text. Code is left
+    /// alone, and so are shortcodes of emojis that don't exist.
+    #[serde(default)]
+    pub emoji: bool,
+    /// Opens links to other sites in a new tab
+    #[serde(default)]
+    pub external_links_new_tab: bool,
+    /// `rel` of links to other sites when `external_links_new_tab` is on
+    #[serde(default = "default_external_links_rel")]
+    pub external_links_rel: String,
+    /// Warns about entries whose `summary` has more characters than this,
+    /// e.g. 160 to keep it whole in search results
+    pub summary_max_length: Option<usize>,
+    /// Generates resized variants of local cover images. Requires the
+    /// `images` feature.
+    pub images: Option<ImagesConfig>,
+    /// Directory the `asset_url` filter reads assets from. Each one is copied
+    /// to `dest_dir` under a name with a hash of its contents, for caching
+    /// them for good. Without it, `asset_url` links to assets as they are.
+    pub hashed_assets_dir: Option<PathBuf>,
+    /// Transliterates slugs to ASCII, e.g. `café` becomes `cafe`. Entry file
+    /// names are slugified too, so `Café au lait.md` is written to
+    /// `cafe-au-lait`. Otherwise, an entry's slug is its file name as is, and
+    /// heading anchors keep their Unicode characters.
+    #[serde(default)]
+    pub ascii_slugs: bool,
+    /// Languages the site is written in, each built from its own source
+    /// directory into its own directory of `dest_dir`.
+    #[serde(default)]
+    pub languages: Vec<Language>,
+    /// Language of the site when it isn't written in several `languages`,
+    /// e.g. `en`
+    pub lang: Option<String>,
+    /// Direction of the site's text, unless its language has its own
+    #[serde(default)]
+    pub dir: TextDirection,
+    /// Names of the pages to build. Every page is built when empty.
+    #[serde(default)]
+    pub only_pages: Vec<String>,
+}
+
+/// Environment a site is built for, so templates can e.g. leave analytics
+/// out of development builds
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteEnv {
+    Dev,
+    #[default]
+    Prod,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UndefinedBehavior {
+    /// Using an undefined variable fails the render, naming the variable and
+    /// the template
+    #[default]
+    Strict,
+    /// Undefined variables render as empty strings
+    Lenient,
+    /// Like `lenient`, but attributes of undefined variables are undefined
+    /// too, rather than an error
+    Chainable,
+}
+
+impl From<UndefinedBehavior> for minijinja::UndefinedBehavior {
+    fn from(behavior: UndefinedBehavior) -> Self {
+        match behavior {
+            UndefinedBehavior::Strict => minijinja::UndefinedBehavior::Strict,
+            UndefinedBehavior::Lenient => minijinja::UndefinedBehavior::Lenient,
+            UndefinedBehavior::Chainable => minijinja::UndefinedBehavior::Chainable,
+        }
+    }
+}
+
+/// A language the site is written in
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Language {
+    /// e.g. `en`
+    pub code: String,
+    /// Directory that the `source_dir`s and `markdown_path`s of the pages'
+    /// entries are relative to in this language
+    pub source_dir: PathBuf,
+    /// Directory of `dest_dir` that the language's pages are written to.
+    /// Defaults to `code`, and can be empty to write them to `dest_dir`
+    /// itself.
+    pub prefix: Option<String>,
+    /// Direction of the language's text. Defaults to the site's `dir`.
+    pub dir: Option<TextDirection>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    /// Left to right
+    #[default]
+    Ltr,
+    /// Right to left
+    Rtl,
+}
+
+impl Language {
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_deref().unwrap_or(self.code.as_str())
+    }
+}
+
+/// Path of an entry's `source_dir` or `markdown_path` in `language`
+fn source_path(language: Option<&Language>, path: &Path) -> PathBuf {
+    match language {
+        Some(language) => language.source_dir.join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Path of `path`, relative to `dest_dir`, in the directory of `language`
+fn language_path(language: Option<&Language>, path: &str) -> String {
+    match language.map(Language::prefix) {
+        Some(prefix) if !prefix.is_empty() => format!("{}/{}", prefix.trim_matches('/'), path),
+        _ => path.to_string(),
+    }
+}
+
+/// Shape of the URLs of generated pages. Every page is written as an
+/// `index.html` in its own directory, which hosts serve under different URLs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlTrailingSlash {
+    /// `/blog/post/`
+    #[default]
+    Always,
+    /// `/blog/post`
+    Never,
+    /// `/blog/post/index.html`
+    IndexHtml,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectStyle {
+    /// A page at every alias that redirects with a `<meta http-equiv="refresh">`
+    #[default]
+    Html,
+    /// A `_redirects` file at the root of `dest_dir`, as read by Netlify and
+    /// Cloudflare Pages
+    Netlify,
+    /// Both of them
+    Both,
+}
+
+/// Headers sent with every file whose path matches `path`
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeaderRule {
+    /// Path of the files, where `*` matches anything, e.g. `/assets/*`
+    pub path: String,
+    pub values: BTreeMap<String, String>,
+}
+
+impl HeaderRule {
+    /// What about the rule hosts wouldn't accept, if anything
+    fn problem(&self) -> Option<&'static str> {
+        if !self.path.starts_with('/') {
+            Some("its path has to start with `/`")
+        } else if self.path.contains(char::is_whitespace) {
+            Some("its path can't have whitespace in it")
+        } else if self.values.is_empty() {
+            Some("it has no headers")
+        } else if self
+            .values
+            .keys()
+            .any(|name| name.is_empty() || name.contains(|c: char| c == ':' || c.is_whitespace()))
+        {
+            Some("header names can't be empty, or have `:` or whitespace in them")
+        } else if self
+            .values
+            .values()
+            .any(|value| value.contains(['\n', '\r']))
+        {
+            Some("header values can't span multiple lines")
+        } else {
+            None
+        }
+    }
+}
+
+/// Contents of a `_headers` file with every rule in `rules`
+fn headers_file(rules: &[HeaderRule]) -> String {
+    let mut file = String::new();
+
+    for rule in rules {
+        file.push_str(rule.path.as_str());
+        file.push('\n');
+
+        for (name, value) in rule.values.iter() {
+            file.push_str(format!("  {}: {}\n", name, value).as_str());
+        }
+    }
+
+    file
+}
+
+fn default_port() -> u16 {
+    3000
+}
+
+fn default_external_links_rel() -> String {
+    "noopener noreferrer nofollow".to_string()
+}
+
+fn default_markdown_extensions() -> Vec<String> {
+    vec!["md".to_string(), "markdown".to_string()]
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -22,12 +331,33 @@ pub struct PageEntries {
     name: String,
     template_path: PathBuf,
     entries: Vec<Entry>,
+    /// Path of every entry under the page, made of tokens like `:year` and
+    /// `:slug`, e.g. `:year/:month/:slug`. Defaults to `:slug`.
+    permalink: Option<String>,
+    /// Directory of `dest_dir` the page and its entries are written to, in
+    /// place of its name, e.g. `posts` for a page named `blog`. Templates
+    /// still refer to the page by its name.
+    output_dir: Option<String>,
+}
+
+impl PageEntries {
+    /// Directory of `dest_dir` the page and its entries are written to
+    fn dir(&self) -> &str {
+        self.output_dir.as_deref().unwrap_or(self.name.as_str())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct StandalonePage {
     name: String,
     template_path: PathBuf,
+    /// File the page is written to, relative to `dest_dir`, e.g. `404.html`.
+    /// Defaults to `<name>/index.html`, or `index.html` for a page named
+    /// `index`.
+    output_path: Option<PathBuf>,
+    /// Directory of `dest_dir` the page is written to as `index.html`, in
+    /// place of its name. `output_path` takes precedence over it.
+    output_dir: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -51,6 +381,149 @@ impl Page {
             Page::Standalone(StandalonePage { name, .. }) => name.as_str(),
         }
     }
+
+    /// Directory of `dest_dir` the page is written to, unless it has an
+    /// `output_path`
+    fn get_dir(&self) -> &str {
+        match self {
+            Page::WithEntries(page) => page.dir(),
+            Page::Standalone(StandalonePage {
+                name, output_dir, ..
+            }) => output_dir.as_deref().unwrap_or(name.as_str()),
+        }
+    }
+
+    fn get_output_path(&self) -> Option<&Path> {
+        match self {
+            Page::WithEntries(_) => None,
+            Page::Standalone(StandalonePage { output_path, .. }) => output_path.as_deref(),
+        }
+    }
+
+    /// Whether the page is the site's homepage, i.e. a standalone page named
+    /// `index` without an `output_path` or `output_dir`
+    fn is_homepage(&self) -> bool {
+        matches!(
+            self,
+            Page::Standalone(StandalonePage {
+                name,
+                output_path: None,
+                output_dir: None,
+                ..
+            }) if name == "index"
+        )
+    }
+
+    /// File the page is written to, relative to `dest_dir`
+    fn output_file(&self) -> PathBuf {
+        match self.get_output_path() {
+            Some(output_path) => output_path.to_path_buf(),
+            None if self.is_homepage() => PathBuf::from("index.html"),
+            None => Path::new(self.get_dir()).join("index.html"),
+        }
+    }
+}
+
+/// Whether `url`, referenced by the entry at `markdown_file`, is a file on the
+/// author's machine rather than part of the site: a `file:` URL, a path in
+/// a home directory or on a Windows drive, an absolute path that exists as
+/// is, or a relative one that climbs out of the project. That's `cwd`, or
+/// the entry's own directory when it lives somewhere else.
+fn is_local_file(url: &str, markdown_file: &Path, cwd: &Path) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let bytes = path.as_bytes();
+
+    let has_drive = bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'/' | b'\\');
+
+    if url.to_ascii_lowercase().starts_with("file:")
+        || path.starts_with("~/")
+        || path.starts_with('\\')
+        || has_drive
+    {
+        return true;
+    }
+
+    // Anything else with a scheme, e.g. `https:` or `mailto:`
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+
+    if path.is_empty() || has_scheme || path.starts_with("//") {
+        return false;
+    }
+
+    // Site-absolute paths, like `/img/cover.jpg`, are fine, unless they
+    // name a file that's on this machine at that very path
+    if path.starts_with('/') {
+        return path != "/" && Path::new(path).is_file();
+    }
+
+    let entry_dir = normalize_path(cwd.join(markdown_file.parent().unwrap_or(Path::new(""))));
+
+    // Relative links are resolved against the entry's directory, wherever
+    // that is, and not the directory puggle happens to run in
+    let project_dir = if entry_dir.starts_with(cwd) {
+        cwd
+    } else {
+        entry_dir.as_path()
+    };
+
+    !normalize_path(entry_dir.join(path)).starts_with(project_dir)
+}
+
+/// `path` with its `.` and `..` components resolved, without touching the
+/// file system
+fn normalize_path(path: PathBuf) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Config files in `dir`, sorted by name
+fn config_fragments(dir: &Path) -> Result<Vec<PathBuf>, config::ConfigError> {
+    let mut fragments: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| config::ConfigError::Foreign(Box::new(e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "yaml" || ext == "yml" || ext == "toml")
+        })
+        .collect();
+
+    if fragments.is_empty() {
+        return Err(config::ConfigError::Message(format!(
+            "config directory {:?} has no YAML or TOML files",
+            dir
+        )));
+    }
+
+    fragments.sort();
+
+    Ok(fragments)
+}
+
+/// Whether `path`, relative to `dest_dir`, would end up outside of it
+fn escapes_dest_dir(path: &Path) -> bool {
+    path.components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -58,371 +531,3544 @@ impl Page {
 enum Entry {
     Dir {
         source_dir: PathBuf,
-        template_path: PathBuf,
+        #[serde(default)]
+        template_path: Option<PathBuf>,
+        /// Front matter shared by every file in `source_dir`. Keys that a
+        /// file defines itself take precedence.
+        #[serde(default)]
+        defaults: Option<serde_yml::Mapping>,
+        /// Copies the other files in `source_dir`, e.g. images, that an
+        /// entry links to into its output directory, so that relative links
+        /// resolve.
+        #[serde(default)]
+        copy_assets: bool,
     },
     File {
         markdown_path: PathBuf,
-        template_path: PathBuf,
+        #[serde(default)]
+        template_path: Option<PathBuf>,
+        #[serde(default)]
+        defaults: Option<serde_yml::Mapping>,
     },
 }
 
 impl Config {
     pub fn from_file() -> Result<Self, config::ConfigError> {
-        let conf = config::Config::builder()
-            .add_source(config::File::with_name("puggle.yaml").required(false))
-            .add_source(config::File::with_name("puggle.yml").required(false))
+        Self::from_path(None)
+    }
+
+    /// Reads the config from `path`, which is either a file, or a directory
+    /// of fragments. Without one, it's read from `puggle.yaml` or
+    /// `puggle.yml`.
+    ///
+    /// Fragments are the directory's YAML and TOML files, merged in the order
+    /// of their names. Keys of later fragments take precedence, except for
+    /// `pages`, which are taken from every fragment.
+    pub fn from_path(path: Option<&Path>) -> Result<Self, config::ConfigError> {
+        let mut builder = config::Config::builder();
+
+        match path {
+            None => {
+                builder = builder
+                    .add_source(config::File::with_name("puggle.yaml").required(false))
+                    .add_source(config::File::with_name("puggle.yml").required(false))
+            }
+            Some(dir) if dir.is_dir() => {
+                let mut pages = Vec::new();
+
+                for fragment in config_fragments(dir)? {
+                    let fragment = config::File::from(fragment.as_path());
+
+                    match config::Config::builder()
+                        .add_source(fragment.clone())
+                        .build()?
+                        .get_array("pages")
+                    {
+                        Ok(fragment_pages) => pages.extend(fragment_pages),
+                        Err(config::ConfigError::NotFound(_)) => {}
+                        Err(e) => return Err(e),
+                    }
+
+                    builder = builder.add_source(fragment);
+                }
+
+                builder = builder.set_override("pages", pages)?;
+            }
+            Some(file) => builder = builder.add_source(config::File::from(file)),
+        }
+
+        let conf = builder
+            // e.g. `PUGGLE_DEST_DIR`, or `PUGGLE_IMAGES__SOURCE_DIR` for
+            // nested keys
+            .add_source(
+                config::Environment::with_prefix("PUGGLE")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true),
+            )
             .build()?;
 
         conf.try_deserialize()
     }
-}
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Metadata {
-    pub title: String,
-    #[serde(with = "time::serde::rfc3339::option")]
-    pub created_at: Option<OffsetDateTime>,
-    #[serde(with = "time::serde::rfc3339::option")]
-    pub updated_at: Option<OffsetDateTime>,
-    #[serde(skip_deserializing)]
-    pub unix_created_at: Option<i64>,
-    #[serde(skip_deserializing)]
-    pub unix_updated_at: Option<i64>,
-    pub tags: Vec<String>,
-    #[serde(skip_deserializing)]
-    pub file_name: String,
-    pub cover: Option<String>,
-    pub summary: Option<String>,
-    pub aliases: Option<Vec<PathBuf>>,
-    pub custom: Option<HashMap<String, String>>,
-}
+    /// URL of the page generated in `path`, a directory relative to
+    /// `dest_dir`. All URLs puggle emits should go through this.
+    pub fn url(&self, path: &str) -> String {
+        let path = self.with_base_path(path);
+        let path = path.as_str();
 
-pub struct TemplateHandle {
-    env: Environment<'static>,
-}
+        match (self.url_trailing_slash, path.is_empty()) {
+            (UrlTrailingSlash::IndexHtml, true) => "/index.html".to_string(),
+            (UrlTrailingSlash::IndexHtml, false) => format!("/{}/index.html", path),
+            (_, true) => "/".to_string(),
+            (UrlTrailingSlash::Always, false) => format!("/{}/", path),
+            (UrlTrailingSlash::Never, false) => format!("/{}", path),
+        }
+    }
 
-impl TemplateHandle {
-    pub fn new(templates_dir: &Path) -> Self {
-        let mut env = minijinja::Environment::new();
-        env.set_loader(minijinja::path_loader(templates_dir));
-        env.add_filter("published_on", published_on);
-        minijinja_contrib::add_to_environment(&mut env);
+    /// URL of a page in `language`
+    fn page_url(&self, page: &Page, language: Option<&Language>) -> String {
+        match page.get_output_path() {
+            Some(output_path) => self
+                .file_url(language_path(language, output_path.to_string_lossy().as_ref()).as_str()),
+            None if page.is_homepage() => self.url(language_path(language, "").as_str()),
+            None => self.url(language_path(language, page.get_dir()).as_str()),
+        }
+    }
 
-        Self { env }
+    /// Every language that's built, or just `None` if there aren't any
+    /// `languages`.
+    fn language_variants(&self) -> Vec<Option<&Language>> {
+        if self.languages.is_empty() {
+            vec![None]
+        } else {
+            self.languages.iter().map(Some).collect()
+        }
     }
-}
 
-#[derive(Debug, Error)]
-pub enum ParseFilesError {
-    #[error("")]
-    Io(#[from] std::io::Error),
-    #[error("")]
-    Parent,
-    #[error("")]
-    FileName,
-    #[error("")]
-    Metadata(#[from] ExtractMetadataError),
-    #[error("failed to load template. reason: {0}")]
-    TemplateEnvironment(minijinja::Error),
-    #[error("failed to render template. reason: {0}")]
-    TemplateRender(minijinja::Error),
-}
+    /// Extensions of every file in a source directory that is an entry
+    fn entry_extensions(&self) -> Vec<String> {
+        [
+            self.markdown_extensions.as_slice(),
+            self.html_extensions.as_slice(),
+        ]
+        .concat()
+    }
 
-pub fn parse(input: &str, opts: pulldown_cmark::Options) -> String {
-    let parser = Parser::new_ext(input, opts);
-    let mut html = String::new();
+    /// Whether the entry at `path` is written in HTML, see `html_extensions`
+    fn is_html_entry(&self, path: &Path) -> bool {
+        let extension = path.extension().unwrap_or(OsStr::new("")).to_string_lossy();
 
-    pulldown_cmark::html::push_html(&mut html, parser.into_iter());
-    html
-}
+        self.html_extensions
+            .iter()
+            .any(|ext| ext.eq_ignore_ascii_case(extension.as_ref()))
+    }
 
-#[derive(Debug, Error)]
-pub enum ExtractMetadataError {
-    #[error("failed to deserialize file \"{0}\" metadata. reason: {1}")]
-    Deserialize(PathBuf, serde_yml::Error),
-}
+    /// `lang` and `dir` of a page in `language`, which an entry's front matter
+    /// may override, for templates to put on the page's `<html>`
+    fn page_context(&self, language: Option<&Language>, metadata: Option<&Metadata>) -> Value {
+        let lang = metadata
+            .and_then(|metadata| metadata.lang.as_deref())
+            .or(language.map(|language| language.code.as_str()))
+            .or(self.lang.as_deref());
 
-pub fn extract_metadata(parser: Parser) -> color_eyre::Result<Option<Metadata>> {
-    let mut metadata = None;
-    let mut record = false;
+        let dir = metadata
+            .and_then(|metadata| metadata.dir)
+            .or(language.and_then(|language| language.dir))
+            .unwrap_or(self.dir);
 
-    for event in parser {
-        if let Event::Start(Tag::MetadataBlock(_)) = event {
-            record = true;
-        }
+        minijinja::context!(lang => lang, dir => dir)
+    }
 
-        match event {
-            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
-                record = true;
-            }
-            Event::End(TagEnd::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
-                break;
-            }
-            Event::Text(txt) => {
-                if record {
-                    metadata = Some(txt.to_string());
-                }
-            }
-            _ => {
-                break;
-            }
-        }
+    /// Whether the page named `name` is part of the build, see `only_pages`
+    pub fn builds_page(&self, name: &str) -> bool {
+        self.only_pages.is_empty() || self.only_pages.iter().any(|page| page == name)
     }
 
-    if let Some(metadata) = metadata {
-        let metadata: Metadata = serde_yml::from_str(metadata.as_str())?;
+    /// URL of the file written to `path`, relative to `dest_dir`
+    pub fn file_url(&self, path: &str) -> String {
+        format!("/{}", self.with_base_path(path.replace('\\', "/").as_str()))
+    }
 
-        let metadata = Metadata {
-            unix_created_at: metadata.created_at.map(|dt| dt.unix_timestamp()),
-            unix_updated_at: metadata.updated_at.map(|dt| dt.unix_timestamp()),
-            ..metadata
-        };
+    /// `base_path` with a leading slash and without a trailing one, e.g.
+    /// `/docs`, or an empty string if the site is served from the root
+    pub fn base_path(&self) -> String {
+        match self.with_base_path("") {
+            base_path if base_path.is_empty() => base_path,
+            base_path => format!("/{}", base_path),
+        }
+    }
 
-        Ok(Some(metadata))
-    } else {
-        Ok(None)
+    /// `path` under `base_path`, without leading or trailing slashes
+    fn with_base_path(&self, path: &str) -> String {
+        let base_path = self.base_path.as_deref().unwrap_or("").trim_matches('/');
+        let path = path.trim_matches('/');
+
+        match (base_path.is_empty(), path.is_empty()) {
+            (true, _) => path.to_string(),
+            (false, true) => base_path.to_string(),
+            (false, false) => format!("{}/{}", base_path, path),
+        }
     }
-}
 
-fn render_entry(
-    inner: String,
-    metadata: &Metadata,
-    template_path: &Path,
-    template_handle: &TemplateHandle,
-) -> Result<String, minijinja::Error> {
-    let template = [
-        format!("{{% extends \"{}\" %}}", template_path.to_string_lossy()),
-        "{% block content %}".to_string(),
-        inner,
-        "{% endblock %}".to_string(),
-    ]
-    .join("\n");
+    /// Looks for problems that would make a build fail or silently produce
+    /// nothing, and reports all of them at once.
+    pub fn validate(&self) -> Result<(), Vec<ConfigIssue>> {
+        let mut issues = Vec::new();
+        let mut page_names = HashSet::new();
+        // Name of the page written to every directory of `dest_dir`
+        let mut page_dirs: HashMap<&str, &str> = HashMap::new();
 
-    let html = template_handle
-        .env
-        .template_from_str(template.as_str())?
-        .render(minijinja::context!(metadata => metadata))?;
+        if !self.templates_dir.is_dir() {
+            issues.push(ConfigIssue::MissingTemplatesDir(self.templates_dir.clone()));
+        }
 
-    Ok(html)
-}
+        if self.pages.is_empty() {
+            issues.push(ConfigIssue::NoPages);
+        }
 
-fn get_markdown_paths(dir: &Path) -> color_eyre::Result<Vec<PathBuf>> {
-    let paths = std::fs::read_dir(dir)?
-        .filter(|entry| {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                path.is_file() && path.extension().unwrap_or(OsStr::new("")) == "md"
-            } else {
-                false
+        for page in self.pages.iter() {
+            if !page_names.insert(page.get_name()) {
+                issues.push(ConfigIssue::DuplicatePageName(page.get_name().to_string()));
             }
-        })
-        .map(|entry| entry.and_then(|entry| Ok(entry.path())))
-        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(paths)
-}
+            self.validate_template(page.get_name(), page.get_template_path(), &mut issues);
 
-pub fn build_from_dir(config: Config) -> color_eyre::Result<()> {
-    let template_handle = TemplateHandle::new(config.templates_dir.as_path());
-    let cmark_opts = pulldown_cmark::Options::all();
-    let mut context: HashMap<&str, Vec<Metadata>> = HashMap::new();
+            if let Some(output_path) = page.get_output_path() {
+                if escapes_dest_dir(output_path) {
+                    issues.push(ConfigIssue::OutputPathOutsideDestDir(
+                        page.get_name().to_string(),
+                        output_path.to_path_buf(),
+                    ));
+                }
+            } else if !page.is_homepage() {
+                let dir = page.get_dir();
 
-    let pages_with_entries: Vec<&PageEntries> =
-        config.pages.iter().fold(Vec::new(), |mut acc, page| {
-            if let Page::WithEntries(page) = page {
-                acc.push(page);
-                acc
-            } else {
-                acc
+                if escapes_dest_dir(Path::new(dir)) {
+                    issues.push(ConfigIssue::OutputPathOutsideDestDir(
+                        page.get_name().to_string(),
+                        PathBuf::from(dir),
+                    ));
+                }
+
+                match page_dirs.insert(dir, page.get_name()) {
+                    // Already reported as a duplicate name
+                    Some(other) if other != page.get_name() => {
+                        issues.push(ConfigIssue::SharedOutputDir(
+                            other.to_string(),
+                            page.get_name().to_string(),
+                            dir.to_string(),
+                        ))
+                    }
+                    _ => {}
+                }
             }
-        });
 
-    for page in pages_with_entries {
-        let mut metadata_list = vec![];
-
-        for entry in page.entries.iter() {
-            match entry {
-                Entry::Dir {
-                    source_dir,
-                    template_path,
-                } => {
-                    let files = get_markdown_paths(source_dir.as_path())?;
-
-                    for file in files {
-                        let markdown = std::fs::read_to_string(file.as_path())?;
-                        let html_partial = parse(markdown.as_str(), cmark_opts);
-                        let parser = Parser::new_ext(markdown.as_str(), cmark_opts);
-                        let metadata = extract_metadata(parser)?;
-
-                        let md_file_name = file
-                            .as_path()
-                            .file_stem()
-                            .ok_or(ParseFilesError::FileName)?;
-
-                        let metadata = metadata
-                            .map(|metadata| Metadata {
-                                file_name: md_file_name.to_string_lossy().to_string(),
-                                ..metadata
-                            })
-                            .ok_or(color_eyre::Report::msg(format!(
-                                "failed to extract metadata from file {:?}",
-                                file.as_path()
-                            )))?;
-
-                        let html = render_entry(
-                            html_partial,
-                            &metadata,
-                            template_path.as_path(),
-                            &template_handle,
-                        )?;
+            let Page::WithEntries(page) = page else {
+                continue;
+            };
 
-                        // Write to file
-                        let target_file = PathBuf::from(config.dest_dir.as_os_str())
-                            .join(page.name.as_str())
-                            .join(md_file_name)
-                            .join("index")
-                            .with_extension("html");
-
-                        if !target_file
-                            .parent()
-                            .ok_or(ParseFilesError::Parent)?
-                            .exists()
+            if let Some(ref permalink) = page.permalink {
+                for token in permalink_tokens(permalink.as_str()) {
+                    if !PERMALINK_TOKENS.contains(&token) {
+                        issues.push(ConfigIssue::UnknownPermalinkToken(
+                            page.name.clone(),
+                            token.to_string(),
+                        ));
+                    }
+                }
+            }
+
+            for (entry, language) in page.entries.iter().flat_map(|entry| {
+                self.language_variants()
+                    .into_iter()
+                    .map(move |language| (entry, language))
+            }) {
+                match entry {
+                    Entry::Dir {
+                        source_dir,
+                        template_path,
+                        ..
+                    } => {
+                        let source_dir = &source_path(language, source_dir);
+
+                        if !source_dir.is_dir() {
+                            issues.push(ConfigIssue::MissingSourceDir(
+                                page.name.clone(),
+                                source_dir.clone(),
+                            ));
+                        } else if get_markdown_paths(source_dir, self.entry_extensions().as_slice())
+                            .map(|paths| paths.is_empty())
+                            .unwrap_or(false)
                         {
-                            std::fs::create_dir_all(
-                                target_file.parent().ok_or(ParseFilesError::Parent)?,
-                            )?;
+                            issues.push(ConfigIssue::EmptySourceDir(
+                                page.name.clone(),
+                                source_dir.clone(),
+                            ));
                         }
 
-                        std::fs::write(target_file, html)?;
-
-                        if let Some(ref aliases) = metadata.aliases {
-                            for alias in aliases {
-                                let alias_file = config
-                                    .dest_dir
-                                    .join(page.name.as_str())
-                                    .join(alias)
-                                    .join("index")
-                                    .with_extension("html");
-
-                                if !alias_file.parent().ok_or(ParseFilesError::Parent)?.exists() {
-                                    std::fs::create_dir_all(
-                                        alias_file.parent().ok_or(ParseFilesError::Parent)?,
-                                    )?;
-                                }
-
-                                let redir_html = format!(
-                                    "<!DOCTYPE html>
-<html>
-  <head>
-    <title>{0}</title>
-    <link rel=\"canonical\" href=\"/{1}\"/>
-    <meta http-equiv=\"content-type\" content=\"text/html; charset=utf-8\"/>
-    <meta http-equiv=\"refresh\" content=\"0; url=/{1}\"/>
-  </head>
-  <body>
-    If you aren't redirected, you can manually click this link:
-    <a href=\"/{1}\">/{1}</a>.
-  </body>
-</html>",
-                                    metadata.title,
-                                    PathBuf::from(page.name.as_str())
-                                        .join(md_file_name)
-                                        .display(),
-                                );
-
-                                std::fs::write(alias_file.as_path(), redir_html)?;
-                            }
+                        if let Some(template_path) = template_path {
+                            self.validate_template(page.name.as_str(), template_path, &mut issues);
                         }
-
-                        metadata_list.push(metadata);
-                    }
-                }
-                Entry::File {
-                    markdown_path,
-                    template_path,
-                } => {
-                    let markdown = std::fs::read_to_string(markdown_path.as_path())?;
-                    let html_partial = parse(markdown.as_str(), cmark_opts);
-                    let parser = Parser::new_ext(markdown.as_str(), cmark_opts);
-                    let metadata = extract_metadata(parser)?;
-
-                    let md_file_name =
-                        markdown_path.file_stem().ok_or(ParseFilesError::FileName)?;
-
-                    let metadata = metadata
-                        .map(|metadata| Metadata {
-                            file_name: md_file_name.to_string_lossy().to_string(),
-                            ..metadata
-                        })
-                        .unwrap();
-
-                    let html = render_entry(
-                        html_partial,
-                        &metadata,
-                        template_path.as_path(),
-                        &template_handle,
-                    )?;
-
-                    // Write to file
-                    let target_file = PathBuf::from(config.dest_dir.as_os_str())
-                        .join(page.name.as_str())
-                        .join(md_file_name)
-                        .join("index")
-                        .with_extension("html");
-
-                    if !target_file
-                        .parent()
-                        .ok_or(ParseFilesError::Parent)?
-                        .exists()
-                    {
-                        std::fs::create_dir_all(
-                            target_file.parent().ok_or(ParseFilesError::Parent)?,
-                        )?;
                     }
+                    Entry::File {
+                        markdown_path,
+                        template_path,
+                        ..
+                    } => {
+                        let markdown_path = &source_path(language, markdown_path);
 
-                    std::fs::write(target_file, html)?;
+                        if !markdown_path.is_file() {
+                            issues.push(ConfigIssue::MissingMarkdownFile(
+                                page.name.clone(),
+                                markdown_path.clone(),
+                            ));
+                        }
 
-                    metadata_list.push(metadata);
+                        if let Some(template_path) = template_path {
+                            self.validate_template(page.name.as_str(), template_path, &mut issues);
+                        }
+                    }
                 }
             }
-
-            context.insert(page.name.as_str(), metadata_list.clone());
         }
-    }
 
-    // Render standalone pages
+        for rule in self.headers.iter() {
+            if let Some(problem) = rule.problem() {
+                issues.push(ConfigIssue::InvalidHeaderRule(rule.path.clone(), problem));
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    fn validate_template(
+        &self,
+        page_name: &str,
+        template_path: &Path,
+        issues: &mut Vec<ConfigIssue>,
+    ) {
+        if !self.templates_dir.join(template_path).is_file() {
+            issues.push(ConfigIssue::MissingTemplate(
+                page_name.to_string(),
+                template_path.to_path_buf(),
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigIssue {
+    #[error("templates directory {0:?} does not exist")]
+    MissingTemplatesDir(PathBuf),
+    #[error("no pages are configured. add some under `pages`")]
+    NoPages,
+    #[error("page \"{0}\" is defined more than once")]
+    DuplicatePageName(String),
+    #[error("pages \"{0}\" and \"{1}\" are both written to directory \"{2}\"")]
+    SharedOutputDir(String, String, String),
+    #[error("page \"{0}\" uses template {1:?}, which does not exist in the templates directory")]
+    MissingTemplate(String, PathBuf),
+    #[error("page \"{0}\" has entries from directory {1:?}, which does not exist")]
+    MissingSourceDir(String, PathBuf),
+    #[error("page \"{0}\" has entries from directory {1:?}, which has no markdown files")]
+    EmptySourceDir(String, PathBuf),
+    #[error("page \"{0}\" has an entry from file {1:?}, which does not exist")]
+    MissingMarkdownFile(String, PathBuf),
+    #[error("page \"{0}\" is written to {1:?}, which is outside of the destination directory")]
+    OutputPathOutsideDestDir(String, PathBuf),
+    #[error("page \"{0}\" has a permalink with unknown token \":{1}\"")]
+    UnknownPermalinkToken(String, String),
+    #[error("headers for \"{0}\" are invalid: {1}")]
+    InvalidHeaderRule(String, &'static str),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Metadata {
+    pub title: String,
+    #[serde(with = "metadata_date")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "metadata_date")]
+    pub updated_at: Option<OffsetDateTime>,
+    #[serde(skip_deserializing)]
+    pub unix_created_at: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub unix_updated_at: Option<i64>,
+    pub tags: Vec<String>,
+    #[serde(skip_deserializing)]
+    pub file_name: String,
+    #[serde(skip_deserializing)]
+    pub url: String,
+    pub cover: Option<Cover>,
+    /// `srcset`s of the resized variants of `cover` for each image format
+    #[serde(skip_deserializing)]
+    pub cover_srcset: Option<BTreeMap<ImageFormat, String>>,
+    pub summary: Option<String>,
+    /// Text of the entry with its markup and code blocks left out, e.g. for
+    /// previews. Template tags in it are left as they are.
+    #[serde(skip_deserializing)]
+    pub plain_text: String,
+    /// Whether the entry has any math, e.g. to only load KaTeX where it's
+    /// needed. Set with `math: true` for math that puggle can't see, like in
+    /// HTML entries.
+    #[serde(default, rename(deserialize = "math"))]
+    pub has_math: bool,
+    /// Whether the entry has any code blocks, e.g. to only load a syntax
+    /// highlighter where it's needed. Can be set with `code: true`.
+    #[serde(default, rename(deserialize = "code"))]
+    pub has_code: bool,
+    pub aliases: Option<Vec<PathBuf>>,
+    pub custom: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub draft: bool,
+    /// Renders the entry, but leaves it out of page listings, `tags`, and the
+    /// search index, so it's only reachable by its URL
+    #[serde(default)]
+    pub unlisted: bool,
+    /// Template the entry is rendered with, which takes precedence over the
+    /// entry's `template_path`
+    pub layout: Option<PathBuf>,
+    pub series: Option<SeriesMembership>,
+    /// Code of the language the entry is written in, if there are `languages`
+    #[serde(skip_deserializing)]
+    pub language: Option<String>,
+    /// Shared by the translations of an entry in other languages
+    pub translation_key: Option<String>,
+    /// Turns straight quotes and dashes into typographic ones. Enabled unless
+    /// set to `false`.
+    pub smart_punctuation: Option<bool>,
+    /// Language the entry is written in, if it's not the one of its site or
+    /// of its `languages` directory
+    pub lang: Option<String>,
+    /// Direction of the entry's text, if it's not the one of its language
+    pub dir: Option<TextDirection>,
+    /// Position of the entry among the others of its page, lowest first.
+    /// Entries without one come after the ones with one, oldest first.
+    pub weight: Option<i64>,
+    /// Directives for search engines' crawlers, e.g. `[noindex, nofollow]`.
+    /// Templates get them as the `content` of a `<meta name="robots">`, which
+    /// is empty for entries that can be indexed as usual.
+    #[serde(default, with = "robots_directives")]
+    pub robots: Vec<String>,
+}
+
+impl Metadata {
+    /// Whether the entry shows up in `pages` and `tags` of page templates, and
+    /// in the search index. Published entries are listed unless `unlisted`.
+    pub fn is_listed(&self) -> bool {
+        !self.unlisted
+    }
+}
+
+/// Dates in front matter, which are either RFC 3339 timestamps, or just dates
+/// like `2024-06-29`, taken as midnight UTC. Always serialized as RFC 3339.
+mod metadata_date {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use time::{format_description::well_known::Iso8601, Date, OffsetDateTime};
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<OffsetDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        time::serde::rfc3339::option::serialize(date, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<OffsetDateTime>, D::Error> {
+        let Some(date) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+
+        OffsetDateTime::parse(
+            date.as_str(),
+            &time::format_description::well_known::Rfc3339,
+        )
+        .or_else(|_| {
+            Date::parse(date.as_str(), &Iso8601::DATE).map(|date| date.midnight().assume_utc())
+        })
+        .map(Some)
+        .map_err(|_| {
+            serde::de::Error::custom(format!(
+                "invalid date \"{}\". expected a date like 2024-06-29, or a timestamp like \
+                    2024-06-29T17:29:00Z",
+                date
+            ))
+        })
+    }
+}
+
+/// Robots directives in front matter, which are either a list, or a single
+/// string like `noindex, nofollow`. Always serialized as the latter.
+mod robots_directives {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Directives {
+        List(Vec<String>),
+        Joined(String),
+    }
+
+    pub fn serialize<S: Serializer>(
+        directives: &[String],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(directives.join(", ").as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        let directives = match Option::<Directives>::deserialize(deserializer)? {
+            Some(Directives::List(directives)) => directives,
+            Some(Directives::Joined(directives)) => {
+                directives.split(',').map(str::to_string).collect()
+            }
+            None => Vec::new(),
+        };
+
+        Ok(directives
+            .iter()
+            .map(|directive| directive.trim().to_ascii_lowercase())
+            .filter(|directive| !directive.is_empty())
+            .collect())
+    }
+}
+
+/// Cover image of an entry. Front matter can give either just its URL, or an
+/// object with its URL, alt text, and caption. Templates always get the
+/// latter, which still renders as just the URL, like covers used to be.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "CoverField")]
+pub struct Cover {
+    pub src: String,
+    /// Left empty for images that are only decorative
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoverField {
+    Src(String),
+    Image {
+        src: String,
+        alt: Option<String>,
+        caption: Option<String>,
+    },
+}
+
+impl From<CoverField> for Cover {
+    fn from(field: CoverField) -> Self {
+        match field {
+            CoverField::Src(src) => Cover {
+                src,
+                alt: None,
+                caption: None,
+            },
+            CoverField::Image { src, alt, caption } => Cover { src, alt, caption },
+        }
+    }
+}
+
+impl Serialize for Cover {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from_object(self.clone()).serialize(serializer)
+    }
+}
+
+impl minijinja::value::Object for Cover {
+    fn repr(self: &Arc<Self>) -> minijinja::value::ObjectRepr {
+        minijinja::value::ObjectRepr::Map
+    }
+
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        match key.as_str()? {
+            "src" => Some(Value::from(self.src.as_str())),
+            "alt" => Some(Value::from(self.alt.clone())),
+            "caption" => Some(Value::from(self.caption.clone())),
+            _ => None,
+        }
+    }
+
+    fn enumerate(self: &Arc<Self>) -> minijinja::value::Enumerator {
+        minijinja::value::Enumerator::Str(&["src", "alt", "caption"])
+    }
+
+    fn render(self: &Arc<Self>, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.src.as_str())
+    }
+}
+
+/// Series an entry is part of. Either just the series' name, or its name
+/// along with the entry's position in it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SeriesMembership {
+    Name(String),
+    Part { name: String, part: Option<u32> },
+}
+
+impl SeriesMembership {
+    pub fn name(&self) -> &str {
+        match self {
+            SeriesMembership::Name(name) => name.as_str(),
+            SeriesMembership::Part { name, .. } => name.as_str(),
+        }
+    }
+
+    pub fn part(&self) -> Option<u32> {
+        match self {
+            SeriesMembership::Name(_) => None,
+            SeriesMembership::Part { part, .. } => *part,
+        }
+    }
+}
+
+pub struct TemplateHandle {
+    env: Environment<'static>,
+    /// Source of every asset `asset_url` hashed during the current build, by
+    /// the path it's written to under `dest_dir`
+    hashed_assets: Arc<Mutex<BTreeMap<PathBuf, PathBuf>>>,
+}
+
+impl TemplateHandle {
+    pub fn new(config: &Config) -> Self {
+        let mut env = minijinja::Environment::new();
+        env.set_loader(minijinja::path_loader(config.templates_dir.as_path()));
+        env.set_undefined_behavior(config.undefined_behavior.into());
+        env.add_filter("published_on", published_on);
+        env.add_filter("word_count", word_count);
+        env.add_filter("reading_time", reading_time);
+        env.add_filter("truncate", truncate_filter);
+        minijinja_contrib::add_to_environment(&mut env);
+
+        let hashed_assets = Arc::new(Mutex::new(BTreeMap::new()));
+
+        {
+            let config = config.clone();
+            let hashed_assets = hashed_assets.clone();
+
+            env.add_filter("asset_url", move |path: String| {
+                asset_url(&config, &hashed_assets, path.as_str())
+            });
+        }
+
+        env.add_global(
+            "site",
+            minijinja::context!(env => config.env.unwrap_or_default()),
+        );
+
+        for (name, value) in config.globals.iter() {
+            env.add_global(name.clone(), Value::from_serialize(value));
+        }
+
+        Self { env, hashed_assets }
+    }
+
+    /// Takes the assets hashed by `asset_url` so far
+    fn take_hashed_assets(&self) -> BTreeMap<PathBuf, PathBuf> {
+        let mut hashed_assets = self
+            .hashed_assets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        std::mem::take(&mut *hashed_assets)
+    }
+
+    /// Drops every template the loader has cached so far, forcing them to be
+    /// read from `templates_dir` again on their next use.
+    pub fn reload_templates(&mut self) {
+        self.env.clear_templates();
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseFilesError {
+    #[error("")]
+    Io(#[from] std::io::Error),
+    #[error("")]
+    Parent,
+    #[error("")]
+    FileName,
+    #[error("")]
+    Metadata(#[from] ExtractMetadataError),
+    #[error("failed to load template. reason: {0}")]
+    TemplateEnvironment(minijinja::Error),
+    #[error("failed to render template. reason: {0:#}")]
+    TemplateRender(minijinja::Error),
+    #[error("page \"{0}\" has entries from directory {1:?}, which does not exist")]
+    MissingSourceDir(String, PathBuf),
+    #[error("there is no page named \"{0}\" to build")]
+    UnknownPage(String),
+    #[error("page \"{0}\" is written to {1:?}, which is outside of the destination directory")]
+    OutputPathOutsideDestDir(String, PathBuf),
+    #[error("pages \"{0}\" and \"{1}\" are both written to directory \"{2}\"")]
+    SharedOutputDir(String, String, String),
+    #[error("failed to make the permalink of {0:?}. reason: {1}")]
+    Permalink(PathBuf, PermalinkError),
+    #[error("headers for \"{0}\" are invalid: {1}")]
+    InvalidHeaderRule(String, &'static str),
+    #[error("alias {0:?} is claimed by both {1:?} and {2:?}")]
+    AliasConflict(PathBuf, PathBuf, PathBuf),
+    #[error("alias {0:?} of {1:?} is where entry {2:?} is written to")]
+    AliasShadowsEntry(PathBuf, PathBuf, PathBuf),
+}
+
+#[derive(Debug, Error)]
+pub enum PermalinkError {
+    #[error("unknown token \":{0}\"")]
+    UnknownToken(String),
+    #[error("token \":{0}\" needs the entry to have a `created_at`")]
+    MissingCreatedAt(String),
+}
+
+/// Tokens a page's `permalink` can be made of
+const PERMALINK_TOKENS: &[&str] = &["year", "month", "day", "slug", "title"];
+
+/// Tokens in a `permalink`, i.e. the letters right after every `:`
+fn permalink_tokens(permalink: &str) -> impl Iterator<Item = &str> {
+    permalink.split(':').skip(1).map(|part| {
+        let end = part
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(part.len());
+
+        &part[..end]
+    })
+}
+
+/// Path of an entry under its page, made by replacing the tokens in
+/// `permalink` with the entry's `slug` and parts of its metadata
+fn expand_permalink(
+    permalink: &str,
+    slug: &str,
+    metadata: &Metadata,
+    ascii: bool,
+) -> Result<String, PermalinkError> {
+    let mut parts = permalink.split(':');
+    let mut path = parts.next().unwrap_or_default().to_string();
+
+    for part in parts {
+        let end = part
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(part.len());
+        let (token, rest) = part.split_at(end);
+        let created_at = || {
+            metadata
+                .created_at
+                .ok_or_else(|| PermalinkError::MissingCreatedAt(token.to_string()))
+        };
+
+        match token {
+            "year" => path.push_str(created_at()?.year().to_string().as_str()),
+            "month" => path.push_str(format!("{:02}", u8::from(created_at()?.month())).as_str()),
+            "day" => path.push_str(format!("{:02}", created_at()?.day()).as_str()),
+            "slug" => path.push_str(slug),
+            "title" => path.push_str(slugify(metadata.title.as_str(), ascii).as_str()),
+            token => return Err(PermalinkError::UnknownToken(token.to_string())),
+        }
+
+        path.push_str(rest);
+    }
+
+    Ok(path.trim_matches('/').to_string())
+}
+
+/// Turns `text` into a lowercase slug, where every run of characters other
+/// than letters, digits, `-`, and `_` becomes a single `-`. With `ascii`,
+/// `text` is transliterated to ASCII first.
+pub fn slugify(text: &str, ascii: bool) -> String {
+    let text = if ascii {
+        deunicode::deunicode(text)
+    } else {
+        text.to_string()
+    };
+
+    let mut slug = String::with_capacity(text.len());
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Extracts the text of a markdown document with its markup, metadata block,
+/// and code blocks left out. Whitespace is collapsed into single spaces.
+pub fn plain_text(input: &str, opts: pulldown_cmark::Options) -> String {
+    scan_markdown(input, opts).text
+}
+
+/// What an entry's markdown is made of, as far as its metadata goes
+struct MarkdownScan {
+    /// See `plain_text`
+    text: String,
+    has_math: bool,
+    has_code: bool,
+    /// Destinations of every link and image
+    references: Vec<String>,
+}
+
+/// Goes through the events of a markdown document once for everything
+/// `MarkdownScan` needs
+fn scan_markdown(input: &str, opts: pulldown_cmark::Options) -> MarkdownScan {
+    let mut text = String::new();
+    let mut skip = false;
+    let mut has_math = false;
+    let mut has_code = false;
+    let mut references = Vec::new();
+    let input = expand_admonitions(input);
+
+    for event in Parser::new_ext(input.as_str(), opts) {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                references.push(dest_url.to_string())
+            }
+            Event::InlineMath(_) | Event::DisplayMath(_) => has_math = true,
+            Event::Start(Tag::CodeBlock(_)) => {
+                has_code = true;
+                skip = true;
+            }
+            Event::Start(Tag::MetadataBlock(_)) => skip = true,
+            Event::End(TagEnd::MetadataBlock(_) | TagEnd::CodeBlock) => skip = false,
+            Event::Text(txt) | Event::Code(txt) if !skip => text.push_str(txt.as_ref()),
+            Event::End(
+                TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Link
+                | TagEnd::Image,
+            ) => {}
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    MarkdownScan {
+        text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        has_math,
+        has_code,
+        references,
+    }
+}
+
+/// Cuts `text` short to at most `length` characters, or `length` words with
+/// `words`, and ends it with an ellipsis. It's cut between words, unless a
+/// single word is already too long. HTML entities, like `&amp;`, count as one
+/// character and are never split. The ellipsis counts towards `length`
+/// characters, but not towards `length` words. Text that fits is only trimmed.
+pub fn truncate(text: &str, length: usize, words: bool) -> String {
+    let text = text.trim();
+
+    let end = if words {
+        words_end(text, length)
+    } else {
+        chars_end(text, length)
+    };
+
+    match end {
+        Some(end) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Where `text` has to be cut to keep `length` characters, ellipsis included,
+/// if it doesn't fit as is
+fn chars_end(text: &str, length: usize) -> Option<usize> {
+    let ends = char_ends(text);
+
+    if ends.len() <= length {
+        return None;
+    }
+
+    let end = match length {
+        0 | 1 => 0,
+        length => ends[length - 2],
+    };
+
+    if text[end..].starts_with(char::is_whitespace) {
+        Some(end)
+    } else {
+        Some(text[..end].rfind(char::is_whitespace).unwrap_or(end))
+    }
+}
+
+/// Where `text` has to be cut to keep `length` words, if it has more
+fn words_end(text: &str, length: usize) -> Option<usize> {
+    let mut count = 0;
+    let mut in_word = false;
+
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            if count == length {
+                return Some(i);
+            }
+
+            count += 1;
+            in_word = true;
+        }
+    }
+
+    None
+}
+
+/// Byte offsets of where each character of `text` ends, with every HTML
+/// entity counted as a single character
+fn char_ends(text: &str) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let end = match c {
+            '&' => entity_len(&text[start..]).map_or(start + 1, |len| start + len),
+            c => start + c.len_utf8(),
+        };
+
+        while chars.next_if(|(i, _)| *i < end).is_some() {}
+
+        ends.push(end);
+    }
+
+    ends
+}
+
+/// Length of the HTML entity `text` starts with, e.g. `&amp;` or `&#8230;`
+fn entity_len(text: &str) -> Option<usize> {
+    let end = text.find(';')?;
+    let name = &text[1..end];
+    let name = name.strip_prefix('#').unwrap_or(name);
+
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())).then_some(end + 1)
+}
+
+/// Changes the markdown events of an entry after puggle's own processing,
+/// right before they're turned into HTML, e.g. to add `rel="nofollow"` to
+/// external links.
+pub type Transform = dyn Fn(&mut Vec<Event<'_>>);
+
+pub fn parse(input: &str, opts: pulldown_cmark::Options, config: &Config) -> String {
+    parse_with(input, opts, config, &[])
+}
+
+/// Same as `parse`, but runs `transforms`, in order, over the events before
+/// they're turned into HTML.
+pub fn parse_with(
+    input: &str,
+    opts: pulldown_cmark::Options,
+    config: &Config,
+    transforms: &[Box<Transform>],
+) -> String {
+    parse_markdown(input, opts, config, &HashMap::new(), transforms).0
+}
+
+/// Same as `parse_with`, but gives the images in `srcsets`, by their `src`,
+/// a `srcset` of their resized variants
+fn parse_markdown(
+    input: &str,
+    opts: pulldown_cmark::Options,
+    config: &Config,
+    srcsets: &HashMap<String, String>,
+    transforms: &[Box<Transform>],
+) -> (String, Vec<TocHeading>) {
+    let input = expand_admonitions(input);
+    let (input, template_tags) = protect_template_tags(input.as_str());
+    let mut events = Vec::new();
+    // Language and source of the passthrough code block being read, if any
+    let mut passthrough: Option<(&str, String)> = None;
+    // Language and source of the code block being read when `copy_code` is
+    // on, since its source has to go on the `<pre>` before the code itself
+    let mut code_block: Option<(String, String)> = None;
+    // Position of the heading being read in `events`, and its text so far
+    let mut heading: Option<(usize, String)> = None;
+    let mut heading_ids: HashSet<String> = HashSet::new();
+    // Every heading read so far, for the table of contents
+    let mut toc: Vec<TocHeading> = Vec::new();
+    // Position of the paragraph being read in `events`
+    let mut paragraph: Option<usize> = None;
+    // Positions of the `[[toc]]` markers in `events`
+    let mut toc_markers: Vec<usize> = Vec::new();
+    // Attributes of the resized image being read, other than its alt text,
+    // and its alt text so far
+    let mut image: Option<(String, String)> = None;
+
+    for event in Parser::new_ext(input.as_str(), opts) {
+        match event {
+            Event::End(TagEnd::Image) if image.is_some() => {
+                if let Some((attrs, alt)) = image.take() {
+                    events.push(Event::InlineHtml(
+                        format!("<img {} alt=\"{}\">", attrs, escape_html(alt.as_str())).into(),
+                    ))
+                }
+            }
+            Event::Text(ref txt) | Event::Code(ref txt) if image.is_some() => {
+                if let Some((_, ref mut alt)) = image {
+                    alt.push_str(txt.as_ref());
+                }
+            }
+            // The alt text of an image is only text
+            _ if image.is_some() => {}
+            Event::Start(Tag::Image {
+                ref dest_url,
+                ref title,
+                ..
+            }) if srcsets.contains_key(dest_url.as_ref()) => {
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    format!(" title=\"{}\"", escape_html(title))
+                };
+                let sizes = config
+                    .images
+                    .as_ref()
+                    .and_then(|images| images.responsive.as_ref())
+                    .map_or("100vw", |responsive| responsive.sizes.as_str());
+
+                image = Some((
+                    format!(
+                        "src=\"{}\" srcset=\"{}\" sizes=\"{}\"{} loading=\"lazy\" \
+                        decoding=\"async\"",
+                        escape_href(dest_url),
+                        escape_html(srcsets[dest_url.as_ref()].as_str()),
+                        escape_html(sizes),
+                        title,
+                    ),
+                    String::new(),
+                ))
+            }
+            Event::Start(Tag::Heading {
+                level,
+                id,
+                classes,
+                attrs,
+            }) => {
+                if let Some(ref id) = id {
+                    heading_ids.insert(id.to_string());
+                }
+
+                heading = Some((events.len(), String::new()));
+
+                events.push(Event::Start(Tag::Heading {
+                    level: offset_heading(level, config.heading_offset),
+                    id,
+                    classes,
+                    attrs,
+                }))
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                let level = offset_heading(level, config.heading_offset);
+
+                if let Some((start, text)) = heading.take() {
+                    if let Event::Start(Tag::Heading { ref mut id, .. }) = events[start] {
+                        if id.is_none() {
+                            *id =
+                                heading_id(text.as_str(), &template_tags, &mut heading_ids, config)
+                                    .map(CowStr::from);
+                        }
+
+                        toc.push(TocHeading {
+                            level,
+                            id: id.as_ref().map(|id| id.to_string()),
+                            text,
+                        });
+                    }
+                }
+
+                events.push(Event::End(TagEnd::Heading(level)))
+            }
+            Event::Start(Tag::Paragraph) => {
+                paragraph = Some(events.len());
+                events.push(event)
+            }
+            Event::End(TagEnd::Paragraph) => match paragraph.take() {
+                Some(start) if is_toc_marker(&events[start + 1..]) => {
+                    events.truncate(start);
+                    toc_markers.push(events.len());
+                    events.push(Event::Html(CowStr::Borrowed("")))
+                }
+                // Display math on its own is a block, rather than part of a
+                // paragraph
+                Some(start) => match display_math(&events[start + 1..]) {
+                    Some(tex) => {
+                        let html =
+                            format!("<div class=\"math display\">{}</div>\n", escape_html(tex));
+
+                        events.truncate(start);
+                        events.push(Event::Html(html.into()))
+                    }
+                    None => events.push(event),
+                },
+                None => events.push(event),
+            },
+            Event::Start(Tag::Link {
+                ref dest_url,
+                ref title,
+                ..
+            }) if config.external_links_new_tab && is_external_link(dest_url) => {
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    format!(" title=\"{}\"", escape_html(title))
+                };
+
+                events.push(Event::InlineHtml(
+                    format!(
+                        "<a href=\"{}\"{} target=\"_blank\" rel=\"{}\">",
+                        escape_href(dest_url),
+                        title,
+                        escape_html(config.external_links_rel.as_str())
+                    )
+                    .into(),
+                ))
+            }
+            Event::Code(ref txt) => {
+                if let Some((_, ref mut text)) = heading {
+                    text.push_str(txt.as_ref());
+                }
+
+                events.push(event)
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
+                let (lang, attrs) = fence_info(info);
+
+                match config
+                    .passthrough_languages
+                    .iter()
+                    .find(|passthrough_lang| passthrough_lang.as_str() == lang)
+                {
+                    Some(lang) => passthrough = Some((lang.as_str(), String::new())),
+                    None => {
+                        let title = attrs
+                            .iter()
+                            .find(|(key, _)| matches!(*key, "title" | "filename"));
+
+                        if let Some((_, title)) = title {
+                            events.push(Event::Html(
+                                format!("<div class=\"code-title\">{}</div>\n", escape_html(title))
+                                    .into(),
+                            ));
+                        }
+
+                        if config.copy_code {
+                            code_block = Some((lang.to_string(), String::new()));
+                        } else {
+                            events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                                lang.to_string().into(),
+                            ))))
+                        }
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) if config.copy_code => {
+                code_block = Some((String::new(), String::new()))
+            }
+            Event::Text(ref txt) => match (&mut passthrough, &mut code_block) {
+                (Some((_, ref mut source)), _) | (None, Some((_, ref mut source))) => {
+                    source.push_str(txt.as_ref())
+                }
+                (None, None) => {
+                    if let Some((_, ref mut text)) = heading {
+                        text.push_str(txt.as_ref());
+                    }
+
+                    events.push(event)
+                }
+            },
+            Event::End(TagEnd::CodeBlock) => match passthrough.take() {
+                Some((lang, source)) => events.push(Event::Html(
+                    format!("<div class=\"{}\">\n{}</div>\n", lang, source).into(),
+                )),
+                None => match code_block.take() {
+                    Some((lang, source)) => {
+                        events.push(Event::Html(copyable_code_block(&lang, &source).into()))
+                    }
+                    None => events.push(event),
+                },
+            },
+            event => events.push(event),
+        }
+    }
+
+    for event in events.iter_mut() {
+        let html = match event {
+            Event::InlineMath(tex) => {
+                format!("<span class=\"math inline\">{}</span>", escape_html(tex))
+            }
+            Event::DisplayMath(tex) => {
+                format!("<span class=\"math display\">{}</span>", escape_html(tex))
+            }
+            _ => continue,
+        };
+
+        *event = Event::InlineHtml(html.into());
+    }
+
+    if config.emoji {
+        let mut in_code_block = false;
+
+        for event in events.iter_mut() {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(TagEnd::CodeBlock) => in_code_block = false,
+                Event::Text(txt) if !in_code_block => {
+                    if let Some(expanded) = expand_emoji(txt) {
+                        *txt = expanded.into();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if !toc_markers.is_empty() {
+        let toc = toc_html(toc.as_slice());
+
+        for marker in toc_markers {
+            events[marker] = Event::Html(toc.clone().into());
+        }
+    }
+
+    for transform in transforms {
+        transform(&mut events);
+    }
+
+    let mut html = String::new();
+
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+
+    // Left as placeholders in the table of contents, since it's part of the
+    // HTML they're put back into, but not in the headings given to templates
+    for heading in toc.iter_mut() {
+        heading.text = without_template_tags(heading.text.as_str(), template_tags.as_slice())
+            .trim()
+            .to_string();
+    }
+
+    (restore_template_tags(html, template_tags.as_slice()), toc)
+}
+
+/// `text` with every `:shortcode:` of a known emoji replaced by the emoji
+/// itself, or `None` if it has none
+fn expand_emoji(text: &str) -> Option<String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+    let mut found = false;
+
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        // Keeps things like `a:b:c` or times from turning into emojis
+        let mid_word = rest[..start]
+            .chars()
+            .next_back()
+            .or_else(|| expanded.chars().next_back())
+            .is_some_and(|c| c.is_alphanumeric());
+
+        let emoji = after
+            .find(':')
+            .filter(|end| {
+                !mid_word
+                    && *end > 0
+                    && after[..*end]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+            })
+            .and_then(|end| Some((end, emojis::get_by_shortcode(&after[..end])?)));
+
+        match emoji {
+            Some((end, emoji)) => {
+                expanded.push_str(&rest[..start]);
+                expanded.push_str(emoji.as_str());
+                rest = &after[end + 1..];
+                found = true;
+            }
+            // The closing colon may still open another shortcode
+            None => {
+                expanded.push_str(&rest[..=start]);
+                rest = after;
+            }
+        }
+    }
+
+    expanded.push_str(rest);
+    found.then_some(expanded)
+}
+
+/// A heading of a document, as listed in its table of contents
+struct TocHeading {
+    level: HeadingLevel,
+    id: Option<String>,
+    text: String,
+}
+
+/// A heading of an entry, as given to its template in `headings_json`
+#[derive(Debug, Serialize)]
+struct HeadingJson<'a> {
+    level: usize,
+    slug: &'a str,
+    text: &'a str,
+}
+
+/// JSON array of the headings of an entry that have an anchor, safe to put
+/// as is in a `<script>`
+fn headings_json(headings: &[TocHeading]) -> Value {
+    let headings = headings
+        .iter()
+        .filter_map(|heading| {
+            Some(HeadingJson {
+                level: heading.level as usize,
+                slug: heading.id.as_deref()?,
+                text: heading.text.as_str(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Serializing plain strings and numbers can't fail
+    let json = serde_json::to_string(&headings).unwrap_or_else(|_| "[]".to_string());
+
+    // Keeps a heading like `</script>` from closing the script it's in
+    Value::from_safe_string(json.replace('<', "\\u003c"))
+}
+
+/// Whether the contents of a paragraph are only a `[[toc]]` or `[TOC]`
+/// marker, which is replaced with the table of contents
+fn is_toc_marker(paragraph: &[Event]) -> bool {
+    let mut text = String::new();
+
+    for event in paragraph {
+        match event {
+            Event::Text(txt) => text.push_str(txt.as_ref()),
+            _ => return false,
+        }
+    }
+
+    matches!(text.trim(), "[[toc]]" | "[TOC]")
+}
+
+/// TeX of the display math a paragraph is made of, if it's made of nothing
+/// else
+fn display_math<'a>(paragraph: &'a [Event]) -> Option<&'a str> {
+    let mut contents = paragraph.iter().filter(|event| match event {
+        Event::SoftBreak | Event::HardBreak => false,
+        Event::Text(txt) => !txt.trim().is_empty(),
+        _ => true,
+    });
+
+    match (contents.next(), contents.next()) {
+        (Some(Event::DisplayMath(tex)), None) => Some(tex.as_ref()),
+        _ => None,
+    }
+}
+
+/// Table of contents of a document, as nested lists of links to its
+/// headings, or nothing if it has none
+fn toc_html(headings: &[TocHeading]) -> String {
+    let mut html = String::new();
+    // Levels of the lists that are still open
+    let mut levels: Vec<HeadingLevel> = Vec::new();
+
+    for heading in headings {
+        match levels.last() {
+            Some(last) if heading.level <= *last => {
+                while levels.len() > 1 && levels.last().is_some_and(|last| heading.level < *last) {
+                    html.push_str("</li></ul>");
+                    levels.pop();
+                }
+
+                html.push_str("</li>");
+            }
+            _ => {
+                html.push_str("<ul>");
+                levels.push(heading.level);
+            }
+        }
+
+        let text = escape_html(heading.text.as_str());
+
+        match heading.id {
+            Some(ref id) => html.push_str(
+                format!("<li><a href=\"#{}\">{}</a>", escape_html(id.as_str()), text).as_str(),
+            ),
+            None => html.push_str(format!("<li>{}", text).as_str()),
+        }
+    }
+
+    if levels.is_empty() {
+        return html;
+    }
+
+    for _ in levels {
+        html.push_str("</li></ul>");
+    }
+
+    format!("<nav class=\"toc\">{}</nav>\n", html)
+}
+
+/// `text` escaped for HTML text and attribute values
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    // Writing to a `String` can't fail
+    let _ = pulldown_cmark_escape::escape_html(&mut escaped, text);
+    escaped
+}
+
+/// `url` escaped for an `href`, the same way markdown links are
+fn escape_href(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    // Writing to a `String` can't fail
+    let _ = pulldown_cmark_escape::escape_href(&mut escaped, url);
+    escaped
+}
+
+/// Whether a link points to another site, i.e. it's an absolute `http` or
+/// `https` URL. Relative links, and ones like `mailto:`, are internal.
+fn is_external_link(dest_url: &str) -> bool {
+    dest_url.starts_with("//")
+        || dest_url.split_once("://").is_some_and(|(scheme, _)| {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        })
+}
+
+/// Renders a code block the way markdown does, but with its raw source in a
+/// `data-code` attribute of the `<pre>` for copy buttons to read from.
+fn copyable_code_block(lang: &str, source: &str) -> String {
+    let class = if lang.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape_html(lang))
+    };
+
+    format!(
+        "<pre data-code=\"{}\"><code{}>{}</code></pre>\n",
+        escape_html(source),
+        class,
+        escape_html(source)
+    )
+}
+
+/// Splits the info string of a fenced code block, like
+/// `rust title="src/main.rs"`, into its language and its `key=value`
+/// attributes. Values with spaces need to be quoted. Any other word after the
+/// language is ignored.
+fn fence_info(info: &str) -> (&str, Vec<(&str, &str)>) {
+    let mut lang = "";
+    let mut attrs = Vec::new();
+    let mut rest = info.trim();
+    let mut first = true;
+
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let (key, after) = rest.split_at(end);
+
+        rest = match after.strip_prefix('=') {
+            Some(after) => {
+                let (value, after) = match after.strip_prefix('"') {
+                    Some(quoted) => match quoted.find('"') {
+                        Some(close) => (&quoted[..close], &quoted[close + 1..]),
+                        None => (quoted, ""),
+                    },
+                    None => after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+                };
+
+                attrs.push((key, value));
+                after
+            }
+            None => {
+                if first {
+                    lang = key;
+                }
+
+                after
+            }
+        }
+        .trim_start();
+
+        first = false;
+    }
+
+    (lang, attrs)
+}
+
+/// Names of the `:::` directives that give their admonition a class of the
+/// same name
+const ADMONITIONS: [&str; 7] = [
+    "note",
+    "tip",
+    "info",
+    "important",
+    "warning",
+    "caution",
+    "danger",
+];
+
+/// Replaces the `:::name Title` and `:::` lines around an admonition with the
+/// HTML of its box, leaving the markdown inside it to be parsed as usual.
+/// Directives with an unknown name still get a box, just without a class of
+/// their own. Anything in the front matter or in a code block is left alone.
+fn expand_admonitions(input: &str) -> String {
+    let body = without_front_matter(input);
+    let mut output = String::with_capacity(input.len());
+    // Number of admonitions that are still open
+    let mut open = 0;
+    // Character and length of the fence of the code block being read
+    let mut fence: Option<(char, usize)> = None;
+
+    output.push_str(&input[..input.len() - body.len()]);
+
+    for line in body.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim();
+
+        if indent > 3 {
+            output.push_str(line);
+            continue;
+        }
+
+        if let Some(marker) = trimmed.chars().next().filter(|c| matches!(c, '`' | '~')) {
+            let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+
+            match fence {
+                Some((open_marker, open_len))
+                    if marker == open_marker
+                        && len >= open_len
+                        && trimmed[len..].trim().is_empty() =>
+                {
+                    fence = None
+                }
+                None if len >= 3 => fence = Some((marker, len)),
+                _ => {}
+            }
+        }
+
+        let directive = trimmed
+            .strip_prefix(":::")
+            .filter(|_| fence.is_none())
+            .map(|directive| directive.trim_start_matches(':').trim());
+
+        match directive {
+            Some("") if open > 0 => {
+                output.push_str("\n</div>\n\n");
+                open -= 1;
+            }
+            Some(directive) if !directive.is_empty() => {
+                let (name, title) = directive
+                    .split_once(char::is_whitespace)
+                    .map(|(name, title)| (name, title.trim()))
+                    .unwrap_or((directive, ""));
+                let name = name.to_lowercase();
+
+                if ADMONITIONS.contains(&name.as_str()) {
+                    output.push_str(format!("<div class=\"admonition {}\">\n", name).as_str());
+                } else {
+                    output.push_str("<div class=\"admonition\">\n");
+                }
+
+                if !title.is_empty() {
+                    output.push_str(
+                        format!("<p class=\"admonition-title\">{}</p>\n", escape_html(title))
+                            .as_str(),
+                    );
+                }
+
+                output.push('\n');
+                open += 1;
+            }
+            // A stray `:::` is left as is
+            _ => output.push_str(line),
+        }
+    }
+
+    // An admonition that's never closed runs to the end of the document
+    for _ in 0..open {
+        output.push_str("\n</div>\n");
+    }
+
+    output
+}
+
+/// Replaces every template tag (`{{ ... }}`, `{% ... %}`, and `{# ... #}`) in
+/// a markdown document with a placeholder, so that markdown doesn't escape or
+/// typeset them before they reach the template engine. A tag has to close
+/// before the next blank line, and tags in inline code and code blocks are
+/// left as they are.
+fn protect_template_tags(input: &str) -> (String, Vec<&str>) {
+    let code: Vec<std::ops::Range<usize>> = Parser::new_ext(
+        input,
+        pulldown_cmark::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS,
+    )
+    .into_offset_iter()
+    .filter(|(event, _)| matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))))
+    .map(|(_, range)| range)
+    .collect();
+    let mut output = String::with_capacity(input.len());
+    let mut tags = Vec::new();
+    // Everything before it is already in `output`
+    let mut copied = 0;
+    let mut next = 0;
+
+    while let Some(start) = input[next..].find('{').map(|start| next + start) {
+        if let Some(code) = code.iter().find(|code| code.contains(&start)) {
+            next = code.end;
+            continue;
+        }
+
+        let closing = match input.as_bytes().get(start + 1) {
+            Some(b'{') => "}}",
+            Some(b'%') => "%}",
+            Some(b'#') => "#}",
+            _ => {
+                next = start + 1;
+                continue;
+            }
+        };
+
+        let limit = code
+            .iter()
+            .map(|code| code.start)
+            .find(|code_start| *code_start > start)
+            .unwrap_or(input.len())
+            .min(paragraph_end(input, start));
+
+        match input[start + 2..limit].find(closing) {
+            Some(end) => {
+                let end = start + 2 + end + closing.len();
+
+                output.push_str(&input[copied..start]);
+                output.push_str(template_tag_placeholder(tags.len()).as_str());
+                tags.push(&input[start..end]);
+                copied = end;
+                next = end;
+            }
+            // Not a tag after all, e.g. a `{#id}` heading attribute
+            None => next = start + 1,
+        }
+    }
+
+    output.push_str(&input[copied..]);
+    (output, tags)
+}
+
+/// Position of the first blank line in `input` after `from`, or the end of
+/// `input` if there's none
+fn paragraph_end(input: &str, from: usize) -> usize {
+    let mut end = from;
+
+    for (i, line) in input[from..].split_inclusive('\n').enumerate() {
+        if i > 0 && line.trim().is_empty() {
+            return end;
+        }
+
+        end += line.len();
+    }
+
+    input.len()
+}
+
+fn restore_template_tags(mut html: String, tags: &[&str]) -> String {
+    for (i, tag) in tags.iter().enumerate() {
+        html = html.replace(template_tag_placeholder(i).as_str(), tag);
+    }
+
+    html
+}
+
+/// `text` with the placeholders of `template_tags` left out
+fn without_template_tags(text: &str, template_tags: &[&str]) -> String {
+    (0..template_tags.len()).fold(text.to_string(), |text, i| {
+        text.replace(template_tag_placeholder(i).as_str(), "")
+    })
+}
+
+fn template_tag_placeholder(i: usize) -> String {
+    format!("PUGGLETEMPLATETAG{}X", i)
+}
+
+/// Anchor of a heading whose text is `text`, made unique among the anchors
+/// in `ids` by a numeric suffix. Template tags in `text` are left out, since
+/// they're only rendered later on.
+fn heading_id(
+    text: &str,
+    template_tags: &[&str],
+    ids: &mut HashSet<String>,
+    config: &Config,
+) -> Option<String> {
+    let text = without_template_tags(text, template_tags);
+    let slug = slugify(text.as_str(), config.ascii_slugs);
+
+    if slug.is_empty() {
+        return None;
+    }
+
+    let id = (0..)
+        .map(|n| match n {
+            0 => slug.clone(),
+            n => format!("{}-{}", slug, n),
+        })
+        .find(|id| !ids.contains(id))?;
+
+    ids.insert(id.clone());
+    Some(id)
+}
+
+fn offset_heading(level: HeadingLevel, offset: u8) -> HeadingLevel {
+    let level = (level as usize).saturating_add(offset as usize).min(6);
+    HeadingLevel::try_from(level).unwrap_or(HeadingLevel::H6)
+}
+
+#[derive(Debug, Error)]
+pub enum ExtractMetadataError {
+    #[error("failed to deserialize file \"{0}\" metadata. reason: {1}")]
+    Deserialize(PathBuf, serde_yml::Error),
+}
+
+/// Extracts the YAML metadata block at the top of a markdown document. Any
+/// top-level key in `defaults` that the document doesn't define is added
+/// before deserializing.
+pub fn extract_metadata(
+    parser: Parser,
+    path: &Path,
+    defaults: Option<&serde_yml::Mapping>,
+) -> Result<Option<Metadata>, ExtractMetadataError> {
+    let mut metadata = None;
+    let mut record = false;
+
+    for event in parser {
+        if let Event::Start(Tag::MetadataBlock(_)) = event {
+            record = true;
+        }
+
+        match event {
+            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
+                record = true;
+            }
+            Event::End(TagEnd::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
+                break;
+            }
+            Event::Text(txt) => {
+                if record {
+                    metadata = Some(txt.to_string());
+                }
+            }
+            _ => {
+                break;
+            }
+        }
+    }
+
+    match metadata {
+        Some(metadata) => {
+            // Keeps the line numbers of errors in line with the file's
+            let metadata = format!("---\n{}", metadata);
+
+            deserialize_metadata(metadata.as_str(), path, defaults).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads just the metadata of the markdown file at `path`, without parsing
+/// the rest of the document. See `extract_metadata`.
+pub fn read_metadata(
+    path: &Path,
+    defaults: Option<&serde_yml::Mapping>,
+) -> color_eyre::Result<Option<Metadata>> {
+    let markdown = std::fs::read_to_string(path)?;
+
+    match front_matter(markdown.as_str()) {
+        Some(front_matter) => Ok(Some(deserialize_metadata(front_matter, path, defaults)?)),
+        None => Ok(None),
+    }
+}
+
+/// Finds the YAML metadata block at the top of a markdown document, delimited
+/// by `---` lines, and closed by either `---` or `...`. Unlike a `Parser`,
+/// this doesn't go through the rest of the document. The opening `---` is
+/// kept, so that it's still valid YAML whose lines match the document's.
+fn front_matter(input: &str) -> Option<&str> {
+    let mut lines = input.split_inclusive('\n');
+
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    let start = input.find('\n')? + 1;
+    let mut end = start;
+
+    for line in lines {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(&input[..end]).filter(|_| !input[start..end].trim().is_empty());
+        }
+
+        end += line.len();
+    }
+
+    None
+}
+
+/// What's left of a document after its front matter, if it has any
+fn without_front_matter(input: &str) -> &str {
+    match front_matter(input) {
+        // The line closing the front matter goes too
+        Some(front_matter) => input[front_matter.len()..]
+            .split_once('\n')
+            .map(|(_, body)| body)
+            .unwrap_or(""),
+        None => input,
+    }
+}
+
+/// Deserializes the YAML metadata block of the markdown file at `path`. Any
+/// top-level key in `defaults` that the block doesn't define is added first.
+fn deserialize_metadata(
+    yaml: &str,
+    path: &Path,
+    defaults: Option<&serde_yml::Mapping>,
+) -> Result<Metadata, ExtractMetadataError> {
+    let error = |e| ExtractMetadataError::Deserialize(path.to_path_buf(), e);
+    let mut metadata: serde_yml::Value = serde_yml::from_str(yaml).map_err(error)?;
+
+    if let (Some(defaults), serde_yml::Value::Mapping(front_matter)) = (defaults, &mut metadata) {
+        for (key, value) in defaults {
+            if !front_matter.contains_key(key) {
+                front_matter.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let metadata: Metadata = serde_yml::from_value(metadata)
+        .map_err(|e| {
+            // Errors of a value don't know where in the file they are, but the
+            // same error in the front matter alone does
+            match serde_yml::from_str::<Metadata>(yaml) {
+                Err(located) if located.to_string().starts_with(e.to_string().as_str()) => located,
+                _ => e,
+            }
+        })
+        .map_err(error)?;
+
+    Ok(Metadata {
+        unix_created_at: metadata.created_at.map(|dt| dt.unix_timestamp()),
+        unix_updated_at: metadata.updated_at.map(|dt| dt.unix_timestamp()),
+        ..metadata
+    })
+}
+
+/// Renders an entry's HTML into the content block of its template. Without a
+/// template, the entry's HTML is rendered as a template of its own, so it may
+/// extend one itself. The entry is compiled under the name of its source file
+/// so that template errors point to it.
+///
+/// Only the entry itself is compiled every time, since its HTML may use
+/// template tags. The template it extends is loaded through the environment,
+/// which compiles it once and keeps it for the rest of the build.
+fn render_entry(
+    inner: String,
+    metadata: &Metadata,
+    context: Value,
+    source_path: &Path,
+    template_path: Option<&Path>,
+    template_handle: &TemplateHandle,
+) -> Result<String, minijinja::Error> {
+    let template = match template_path {
+        // The wrapping tags share the first line so that line numbers in
+        // errors are only off by one from the entry's HTML
+        Some(template_path) => [
+            format!(
+                "{{% extends \"{}\" %}}{{% block content %}}",
+                template_path.to_string_lossy()
+            ),
+            inner,
+            "{% endblock %}".to_string(),
+        ]
+        .join("\n"),
+        None => inner,
+    };
+
+    let name = source_path.to_string_lossy();
+
+    let html = template_handle
+        .env
+        .template_from_named_str(name.as_ref(), template.as_str())?
+        .render(minijinja::context!(
+            metadata => metadata,
+            is_draft => metadata.draft,
+            ..context
+        ))?;
+
+    Ok(html)
+}
+
+/// Banner injected into drafts when `draft_banner` is enabled
+const DRAFT_BANNER: &str = "<div class=\"puggle-draft-banner\" style=\"padding: 0.5rem; \
+    background: #fde68a; color: #78350f; text-align: center; font-weight: bold;\">\
+    Draft: this page won't be published</div>";
+
+/// Inserts the draft banner right after the opening `<body>` tag, or at the
+/// very start of the document if there is none.
+fn inject_draft_banner(html: &str) -> String {
+    let position = html
+        .find("<body")
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+
+    [&html[..position], DRAFT_BANNER, &html[position..]].concat()
+}
+
+/// Removes trailing whitespace from every line, collapses runs of blank lines
+/// into one, and ends `html` with a single newline. Lines inside `<pre>`
+/// blocks are kept as is since their whitespace is significant. Normalizing
+/// already normalized HTML doesn't change it.
+fn normalize_html(html: &str) -> String {
+    let mut normalized = String::with_capacity(html.len() + 1);
+    let mut in_pre = false;
+    let mut previous_blank = true;
+
+    for line in html.lines() {
+        let starts_in_pre = in_pre;
+
+        in_pre = match (line.rfind("<pre"), line.rfind("</pre>")) {
+            (Some(open), Some(close)) => open > close,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => in_pre,
+        };
+
+        if starts_in_pre || in_pre {
+            normalized.push_str(line);
+            normalized.push('\n');
+            previous_blank = false;
+            continue;
+        }
+
+        let line = line.trim_end();
+
+        if line.is_empty() && previous_blank {
+            continue;
+        }
+
+        previous_blank = line.is_empty();
+        normalized.push_str(line);
+        normalized.push('\n');
+    }
+
+    while normalized.ends_with("\n\n") {
+        normalized.pop();
+    }
+
+    normalized
+}
+
+fn get_markdown_paths(dir: &Path, extensions: &[String]) -> color_eyre::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .filter(|entry| {
+            if let Ok(entry) = entry {
+                let path = entry.path();
+                let extension = path.extension().unwrap_or(OsStr::new("")).to_string_lossy();
+
+                path.is_file()
+                    && extensions
+                        .iter()
+                        .any(|ext| ext.eq_ignore_ascii_case(extension.as_ref()))
+            } else {
+                false
+            }
+        })
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // `read_dir` makes no promises about its order
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Lists every file under `dir` that isn't a markdown file or hidden,
+/// recursively. Paths are relative to `dir`.
+fn get_asset_paths(dir: &Path, extensions: &[String]) -> color_eyre::Result<Vec<PathBuf>> {
+    let markdown_paths = get_markdown_paths(dir, extensions)?;
+    let mut paths = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            || markdown_paths.contains(&path)
+        {
+            continue;
+        }
+
+        let relative_path = path.strip_prefix(dir)?.to_path_buf();
+
+        if path.is_dir() {
+            for nested_path in get_asset_paths(path.as_path(), extensions)? {
+                paths.push(relative_path.join(nested_path));
+            }
+        } else if path.is_file() {
+            paths.push(relative_path);
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+pub fn build_from_dir(config: Config) -> color_eyre::Result<BuildReport> {
+    let template_handle = TemplateHandle::new(&config);
+    build_from_dir_with_templates(config, &template_handle)
+}
+
+/// Same as `build_from_dir`, but runs `transforms` over the markdown events
+/// of every entry. See `parse_with`.
+pub fn build_from_dir_with(
+    config: Config,
+    transforms: &[Box<Transform>],
+) -> color_eyre::Result<BuildReport> {
+    let template_handle = TemplateHandle::new(&config);
+    build(config, &template_handle, transforms)
+}
+
+/// Same as `build_from_dir`, but renders with an existing `TemplateHandle`
+/// rather than setting up a new environment. Useful when building repeatedly.
+pub fn build_from_dir_with_templates(
+    config: Config,
+    template_handle: &TemplateHandle,
+) -> color_eyre::Result<BuildReport> {
+    build(config, template_handle, &[])
+}
+
+fn build(
+    config: Config,
+    template_handle: &TemplateHandle,
+    transforms: &[Box<Transform>],
+) -> color_eyre::Result<BuildReport> {
+    let mut build = Build {
+        config: &config,
+        template_handle,
+        transforms,
+        cmark_opts: pulldown_cmark::Options::all(),
+        report: BuildReport::default(),
+        search_index: Vec::new(),
+        redirects: Vec::new(),
+        image_cache: BTreeMap::new(),
+        started_at: OffsetDateTime::now_utc(),
+        profile: BuildProfile::default(),
+    };
+    let started = Instant::now();
+
+    // Left over from a previous build that failed halfway through
+    template_handle.take_hashed_assets();
+
+    if let Some(name) = config.only_pages.iter().find(|name| {
+        !config
+            .pages
+            .iter()
+            .any(|page| page.get_name() == name.as_str())
+    }) {
+        return Err(ParseFilesError::UnknownPage(name.clone()).into());
+    }
+
+    if config.pages.is_empty() {
+        build.report.warnings.push(BuildWarning::NoPages);
+    }
+
+    if let Some((rule, problem)) = config
+        .headers
+        .iter()
+        .find_map(|rule| rule.problem().map(|problem| (rule, problem)))
+    {
+        return Err(ParseFilesError::InvalidHeaderRule(rule.path.clone(), problem).into());
+    }
+
+    let mut page_dirs: HashMap<&str, &str> = HashMap::new();
+
     for page in config.pages.iter() {
-        let template_path = page
-            .get_template_path()
-            .to_str()
-            .ok_or(color_eyre::Report::msg(
-                "page template path is not a valid unicode",
-            ))?;
-
-        let html = template_handle
-            .env
-            .get_template(template_path)
-            .map_err(|e| ParseFilesError::TemplateEnvironment(e))?
-            .render(minijinja::context!(pages => context))
-            .map_err(|e| ParseFilesError::TemplateRender(e))?;
-
-        let target_file = PathBuf::from(config.dest_dir.as_path())
-            .join(page.get_name())
-            .join("index")
-            .with_extension("html");
-
-        if !target_file
-            .parent()
-            .ok_or(ParseFilesError::Parent)?
-            .exists()
+        if let Some(output_path) = page.get_output_path() {
+            if escapes_dest_dir(output_path) {
+                return Err(ParseFilesError::OutputPathOutsideDestDir(
+                    page.get_name().to_string(),
+                    output_path.to_path_buf(),
+                )
+                .into());
+            }
+        } else if !page.is_homepage() {
+            let dir = page.get_dir();
+
+            if escapes_dest_dir(Path::new(dir)) {
+                return Err(ParseFilesError::OutputPathOutsideDestDir(
+                    page.get_name().to_string(),
+                    PathBuf::from(dir),
+                )
+                .into());
+            }
+
+            if let Some(other) = page_dirs.insert(dir, page.get_name()) {
+                return Err(ParseFilesError::SharedOutputDir(
+                    other.to_string(),
+                    page.get_name().to_string(),
+                    dir.to_string(),
+                )
+                .into());
+            }
+        }
+    }
+
+    let pages_with_entries: Vec<&PageEntries> =
+        config.pages.iter().fold(Vec::new(), |mut acc, page| {
+            if let Page::WithEntries(page) = page {
+                acc.push(page);
+                acc
+            } else {
+                acc
+            }
+        });
+
+    // Every entry is read before any of them is rendered, since an entry may
+    // need to know about the others, e.g. the other parts of its series. This
+    // includes the entries of pages left out of the build.
+    let mut sources: Vec<SourceEntry> = Vec::new();
+
+    for language in config.language_variants() {
+        for page in pages_with_entries.iter() {
+            for entry in page.entries.iter() {
+                match entry {
+                    Entry::Dir {
+                        source_dir,
+                        template_path,
+                        defaults,
+                        copy_assets,
+                    } => {
+                        let source_dir = source_path(language, source_dir);
+
+                        if !source_dir.is_dir() {
+                            return Err(ParseFilesError::MissingSourceDir(
+                                page.name.clone(),
+                                source_dir,
+                            )
+                            .into());
+                        }
+
+                        let files = get_markdown_paths(
+                            source_dir.as_path(),
+                            config.entry_extensions().as_slice(),
+                        )?;
+
+                        if files.is_empty() {
+                            build.report.warnings.push(BuildWarning::EmptySourceDir(
+                                page.name.clone(),
+                                source_dir.clone(),
+                            ));
+                        }
+
+                        let assets = if *copy_assets {
+                            get_asset_paths(
+                                source_dir.as_path(),
+                                config.entry_extensions().as_slice(),
+                            )?
+                        } else {
+                            Vec::new()
+                        };
+
+                        for file in files {
+                            let source = build.read_markdown_file(
+                                page,
+                                language,
+                                file.clone(),
+                                template_path.as_deref(),
+                                defaults.as_ref(),
+                            );
+
+                            let source = build.keep_going(file.as_path(), source)?.flatten();
+
+                            sources.extend(source.map(|source| SourceEntry {
+                                asset_dir: Some(source_dir.clone()),
+                                assets: referenced_assets(assets.as_slice(), &source),
+                                ..source
+                            }));
+                        }
+                    }
+                    Entry::File {
+                        markdown_path,
+                        template_path,
+                        defaults,
+                    } => {
+                        let file = source_path(language, markdown_path);
+                        let source = build.read_markdown_file(
+                            page,
+                            language,
+                            file.clone(),
+                            template_path.as_deref(),
+                            defaults.as_ref(),
+                        );
+
+                        sources.extend(build.keep_going(file.as_path(), source)?.flatten());
+                    }
+                }
+            }
+        }
+    }
+
+    // Entries with a weight come first, then the rest by creation date, and
+    // entries without one last. The sort is stable, so entries that are
+    // still tied stay in the order they were read in, i.e. by file name.
+    sources.sort_by_key(|source| {
+        let metadata = &source.metadata;
+
+        (
+            metadata.weight.is_none(),
+            metadata.weight,
+            metadata.created_at.is_none(),
+            metadata.created_at,
+        )
+    });
+
+    check_aliases(sources.as_slice())?;
+
+    let series = collect_series(sources.as_slice());
+    let backlinks = collect_backlinks(sources.as_slice());
+
+    for (i, source) in sources.iter().enumerate() {
+        if !config.builds_page(source.page.name.as_str()) {
+            continue;
+        }
+
+        // Defined for every entry, even if it's empty, so that strict
+        // templates can still check for it
+        let series_context = series.iter().find_map(|((_, name), parts)| {
+            let position = parts.iter().position(|part| *part == i)?;
+
+            Some(SeriesContext {
+                name,
+                parts: parts.iter().map(|part| &sources[*part].metadata).collect(),
+                position: position + 1,
+                total: parts.len(),
+            })
+        });
+
+        let rendered = build.render_source_entry(
+            source,
+            minijinja::context!(
+                series => series_context,
+                language => source.language.map(|language| language.code.as_str()),
+                translations => translations(sources.as_slice(), i),
+                backlinks => backlinks[i]
+                    .iter()
+                    .map(|source| Backlink {
+                        title: sources[*source].metadata.title.as_str(),
+                        url: sources[*source].metadata.url.as_str(),
+                    })
+                    .collect::<Vec<_>>(),
+                page => config.page_context(source.language, Some(&source.metadata)),
+                ..build.time_context()
+            ),
+        );
+
+        build.keep_going(source.path.as_path(), rendered)?;
+    }
+
+    // Render standalone pages, once for every language
+    for language in config.language_variants() {
+        // Ordered so that templates iterating over it render the same every
+        // time
+        let mut context: BTreeMap<&str, Vec<Metadata>> = pages_with_entries
+            .iter()
+            .map(|page| (page.name.as_str(), Vec::new()))
+            .collect();
+
+        // Tags are lowercased, so that differently cased spellings of a tag
+        // are counted together
+        let mut tags: BTreeMap<String, TagContext> = BTreeMap::new();
+
+        for source in sources
+            .iter()
+            .filter(|source| source.language == language && source.metadata.is_listed())
+        {
+            context
+                .entry(source.page.name.as_str())
+                .or_default()
+                .push(source.metadata.clone());
+
+            let entry_tags: BTreeSet<String> = source
+                .metadata
+                .tags
+                .iter()
+                .map(|tag| tag.to_lowercase())
+                .collect();
+
+            for tag in entry_tags {
+                let tag = tags.entry(tag).or_default();
+
+                tag.count += 1;
+                tag.entries.push(&source.metadata);
+            }
+        }
+
+        for page in config.pages.iter() {
+            if !config.builds_page(page.get_name()) {
+                continue;
+            }
+
+            let template_path =
+                page.get_template_path()
+                    .to_str()
+                    .ok_or(color_eyre::Report::msg(
+                        "page template path is not a valid unicode",
+                    ))?;
+
+            let languages: Vec<LanguageLink> = config
+                .languages
+                .iter()
+                .map(|language| LanguageLink {
+                    code: language.code.as_str(),
+                    url: config.page_url(page, Some(language)),
+                })
+                .collect();
+
+            let rendering = Instant::now();
+            let html = template_handle
+                .env
+                .get_template(template_path)
+                .map_err(ParseFilesError::TemplateEnvironment)?
+                .render(minijinja::context!(
+                    pages => context,
+                    tags => tags,
+                    base_path => config.base_path(),
+                    language => language.map(|language| language.code.as_str()),
+                    languages => languages,
+                    page => config.page_context(language, None),
+                    ..build.time_context()
+                ))
+                .map_err(ParseFilesError::TemplateRender)?;
+            build.profile.render += rendering.elapsed();
+
+            let target_file = config.dest_dir.join(language_path(
+                language,
+                page.output_file().to_string_lossy().as_ref(),
+            ));
+
+            build.write_output(target_file.as_path(), html.as_bytes(), None, None)?;
+        }
+    }
+
+    for (hashed, source) in template_handle.take_hashed_assets() {
+        let contents = std::fs::read(source.as_path())?;
+        let target_file = config.dest_dir.join(hashed);
+
+        build.write_output(
+            target_file.as_path(),
+            contents.as_slice(),
+            Some(source.as_path()),
+            None,
+        )?;
+    }
+
+    // These would be missing whatever was left out of the build
+    let partial = !config.only_pages.is_empty();
+
+    if config.search_index && !partial {
+        let search_index = serde_json::to_vec(&build.search_index)?;
+        let target_file = config.dest_dir.join(SEARCH_INDEX_FILE_NAME);
+
+        build.write_output(target_file.as_path(), search_index.as_slice(), None, None)?;
+    }
+
+    if !config.headers.is_empty() {
+        let headers = headers_file(config.headers.as_slice());
+        let target_file = config.dest_dir.join(HEADERS_FILE_NAME);
+
+        build.write_output(target_file.as_path(), headers.as_bytes(), None, None)?;
+    }
+
+    if config.redirect_style != RedirectStyle::Html && !partial {
+        let redirects: String = build
+            .redirects
+            .iter()
+            .map(|(from, to)| format!("{} {} 301\n", from, to))
+            .collect();
+        let target_file = config.dest_dir.join(REDIRECTS_FILE_NAME);
+
+        build.write_output(target_file.as_path(), redirects.as_bytes(), None, None)?;
+    }
+
+    if config.manifest && !config.dry_run && !partial {
+        let manifest = serde_json::to_vec_pretty(&build.report)?;
+        std::fs::write(config.dest_dir.join(MANIFEST_FILE_NAME), manifest)?;
+    }
+
+    if config.profile {
+        build.profile.total = started.elapsed();
+        build.report.profile = Some(build.profile);
+    }
+
+    Ok(build.report)
+}
+
+/// File name of the build manifest, written at the root of `dest_dir`.
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// File name of the headers of `headers`, written at the root of `dest_dir`.
+const HEADERS_FILE_NAME: &str = "_headers";
+
+/// File name of the redirects of every alias, written at the root of
+/// `dest_dir`.
+const REDIRECTS_FILE_NAME: &str = "_redirects";
+
+/// File name of the search index, written at the root of `dest_dir`.
+const SEARCH_INDEX_FILE_NAME: &str = "search-index.json";
+
+/// An entry's record in the search index
+#[derive(Debug, Serialize)]
+pub struct SearchIndexEntry {
+    pub url: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub summary: Option<String>,
+    pub body: String,
+}
+
+/// Everything a build wrote to `dest_dir`, and anything worth pointing out
+/// that didn't stop it.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    pub files: Vec<OutputFile>,
+    pub warnings: Vec<BuildWarning>,
+    /// Entries that failed to build, with `keep_going`
+    pub errors: Vec<EntryError>,
+    /// How long each phase of the build took, with `profile`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<BuildProfile>,
+}
+
+/// Time a build spent in each of its phases, added up over every entry and
+/// page. Serialized in milliseconds.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildProfile {
+    /// Reading entry files and their front matter
+    #[serde(serialize_with = "serialize_millis")]
+    pub read: Duration,
+    /// Turning markdown into HTML
+    #[serde(serialize_with = "serialize_millis")]
+    pub parse: Duration,
+    /// Rendering the templates of entries and pages
+    #[serde(serialize_with = "serialize_millis")]
+    pub render: Duration,
+    /// Resizing cover images
+    #[serde(serialize_with = "serialize_millis")]
+    pub images: Duration,
+    /// Writing files to `dest_dir`, or comparing them with what's there
+    #[serde(serialize_with = "serialize_millis")]
+    pub write: Duration,
+    /// The whole build, including what's in none of the phases above
+    #[serde(serialize_with = "serialize_millis")]
+    pub total: Duration,
+}
+
+impl BuildProfile {
+    /// Name and duration of every phase, in the order they're listed in
+    pub fn phases(&self) -> [(&'static str, Duration); 6] {
+        [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("render", self.render),
+            ("images", self.images),
+            ("write", self.write),
+            ("total", self.total),
+        ]
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// An entry that failed to build, and why
+#[derive(Debug, Serialize)]
+pub struct EntryError {
+    /// Source file of the entry
+    pub path: PathBuf,
+    pub message: String,
+}
+
+#[derive(Debug, Error)]
+pub enum BuildWarning {
+    #[error("page \"{0}\" has entries from directory {1:?}, which has no markdown files")]
+    EmptySourceDir(String, PathBuf),
+    #[error("{0:?} references image \"{1}\", which does not exist")]
+    MissingImage(PathBuf, String),
+    #[error("images are configured, but puggle was built without the `images` feature")]
+    ImagesDisabled,
+    #[error("no pages are configured, so there is nothing to build. add some under `pages`")]
+    NoPages,
+    #[error("{0:?} references \"{1}\", a file on this machine that won't be deployed")]
+    LocalFile(PathBuf, String),
+    #[error("{0:?} has a cover without alt text. give it one with `cover: {{ src, alt }}`")]
+    MissingCoverAlt(PathBuf),
+    #[error("{0:?} has a summary of {1} characters, more than the {2} of `summary_max_length`")]
+    LongSummary(PathBuf, usize, usize),
+}
+
+impl Serialize for BuildWarning {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutputFile {
+    /// Path of the generated file, relative to `dest_dir`
+    pub path: PathBuf,
+    /// Markdown file the output was generated from, if any
+    pub source: Option<PathBuf>,
+    /// Hex-encoded SHA-256 of the file's contents
+    pub hash: String,
+    pub size: usize,
+    pub change: FileChange,
+    pub entry: Option<OutputEntry>,
+}
+
+/// How writing an output file changed what was in `dest_dir`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+impl std::fmt::Display for FileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            FileChange::Created => "create",
+            FileChange::Updated => "update",
+            FileChange::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// Key metadata of the entry an output file was rendered from
+#[derive(Debug, Serialize)]
+pub struct OutputEntry {
+    pub title: String,
+    pub slug: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+impl From<&Metadata> for OutputEntry {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            title: metadata.title.clone(),
+            slug: metadata.file_name.clone(),
+            url: metadata.url.clone(),
+            tags: metadata.tags.clone(),
+            created_at: metadata.created_at,
+            updated_at: metadata.updated_at,
+        }
+    }
+}
+
+/// A markdown file to be rendered as an entry of a page
+struct SourceEntry<'a> {
+    page: &'a PageEntries,
+    language: Option<&'a Language>,
+    path: PathBuf,
+    /// Output directory of the entry, relative to `dest_dir`
+    dir: PathBuf,
+    template_path: Option<&'a Path>,
+    markdown: String,
+    metadata: Metadata,
+    /// Directory that `assets` are relative to
+    asset_dir: Option<PathBuf>,
+    /// Files copied into the entry's output directory, the ones in
+    /// `asset_dir` that it links to
+    assets: Vec<PathBuf>,
+    /// Destinations of the entry's links and images, as they're written
+    links: Vec<String>,
+}
+
+/// Which of `assets`, the files next to an entry, the entry links to or has
+/// as its cover, relative to itself
+fn referenced_assets(assets: &[PathBuf], source: &SourceEntry) -> Vec<PathBuf> {
+    let referenced: BTreeSet<String> = source
+        .links
+        .iter()
+        .map(String::as_str)
+        .chain(
+            source
+                .metadata
+                .cover
+                .as_ref()
+                .map(|cover| cover.src.as_str()),
+        )
+        .filter(|reference| !reference.starts_with('/'))
+        .filter_map(|reference| link_target(reference, ""))
+        .collect();
+
+    assets
+        .iter()
+        .filter(|asset| {
+            let path = asset
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            link_target(path.as_str(), "").is_some_and(|path| referenced.contains(&path))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Checks that no two entries claim the same alias, and that no alias is
+/// where an entry is written to, since either would overwrite a page.
+fn check_aliases(sources: &[SourceEntry]) -> Result<(), ParseFilesError> {
+    // Source of the entry every alias redirects to, by the directory it's
+    // written to
+    let mut aliases: HashMap<PathBuf, &Path> = HashMap::new();
+
+    for source in sources {
+        for alias in source.metadata.aliases.iter().flatten() {
+            let alias_dir =
+                PathBuf::from(language_path(source.language, source.page.dir())).join(alias);
+
+            match aliases.insert(alias_dir.clone(), source.path.as_path()) {
+                Some(other) if other != source.path => {
+                    return Err(ParseFilesError::AliasConflict(
+                        alias_dir,
+                        other.to_path_buf(),
+                        source.path.clone(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for source in sources {
+        if let Some(alias_source) = aliases.get(&source.dir) {
+            return Err(ParseFilesError::AliasShadowsEntry(
+                source.dir.clone(),
+                alias_source.to_path_buf(),
+                source.path.clone(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Groups entries by the series they're part of, in reading order. Entries
+/// with an explicit `part` come first, followed by the rest by creation date.
+fn collect_series<'a>(
+    sources: &'a [SourceEntry],
+) -> BTreeMap<(Option<&'a str>, &'a str), Vec<usize>> {
+    let mut series: BTreeMap<(Option<&str>, &str), Vec<usize>> = BTreeMap::new();
+
+    for (i, source) in sources.iter().enumerate() {
+        if let Some(ref membership) = source.metadata.series {
+            let language = source.language.map(|language| language.code.as_str());
+
+            series
+                .entry((language, membership.name()))
+                .or_default()
+                .push(i);
+        }
+    }
+
+    for parts in series.values_mut() {
+        parts.sort_by_key(|i| {
+            let metadata = &sources[*i].metadata;
+
+            (
+                metadata
+                    .series
+                    .as_ref()
+                    .and_then(SeriesMembership::part)
+                    .unwrap_or(u32::MAX),
+                metadata.created_at,
+            )
+        });
+    }
+
+    series
+}
+
+/// Versions of `sources[i]` in other languages, i.e. the entries sharing its
+/// `translation_key`
+fn translations<'a>(sources: &'a [SourceEntry], i: usize) -> Vec<Translation<'a>> {
+    let Some(ref key) = sources[i].metadata.translation_key else {
+        return Vec::new();
+    };
+
+    sources
+        .iter()
+        .filter(|source| source.language != sources[i].language)
+        .filter(|source| source.metadata.translation_key.as_ref() == Some(key))
+        .filter_map(|source| {
+            Some(Translation {
+                language: source.language?.code.as_str(),
+                title: source.metadata.title.as_str(),
+                url: source.metadata.url.as_str(),
+            })
+        })
+        .collect()
+}
+
+/// Entries using a tag, across every page
+#[derive(Debug, Default, Serialize)]
+struct TagContext<'a> {
+    count: usize,
+    entries: Vec<&'a Metadata>,
+}
+
+/// A link to one of the directories above an entry, or to the entry itself
+#[derive(Debug, Serialize)]
+struct Breadcrumb<'a> {
+    name: &'a str,
+    url: String,
+}
+
+/// For every entry, the other entries that link to it, in the order they
+/// were read in
+fn collect_backlinks(sources: &[SourceEntry]) -> Vec<Vec<usize>> {
+    let by_path: HashMap<String, usize> = sources
+        .iter()
+        .enumerate()
+        .filter_map(|(i, source)| Some((link_target(source.metadata.url.as_str(), "/")?, i)))
+        .collect();
+
+    let mut backlinks = vec![Vec::new(); sources.len()];
+
+    for (i, source) in sources.iter().enumerate() {
+        let targets: BTreeSet<usize> = source
+            .links
+            .iter()
+            .filter_map(|link| link_target(link, source.metadata.url.as_str()))
+            .filter_map(|path| by_path.get(&path).copied())
+            .filter(|target| *target != i)
+            .collect();
+
+        for target in targets {
+            backlinks[target].push(i);
+        }
+    }
+
+    backlinks
+}
+
+/// Path of the page that `url`, a link on the page at `from`, points to,
+/// without `index.html` or slashes at either end, e.g. `blog/first`. `None`
+/// for links to other sites, and to somewhere on the same page.
+fn link_target(url: &str, from: &str) -> Option<String> {
+    let has_scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains(['/', '?', '#']));
+
+    if has_scheme || url.starts_with("//") {
+        return None;
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    if path.is_empty() {
+        return None;
+    }
+
+    // Relative links are resolved like browsers do, against the directory
+    // of the page they're on
+    let joined = match path.strip_prefix('/') {
+        Some(path) => path.to_string(),
+        None => {
+            let dir = from.rsplit_once('/').map_or("", |(dir, _)| dir);
+            format!("{}/{}", dir, path)
+        }
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.last() == Some(&"index.html") {
+        segments.pop();
+    }
+
+    Some(segments.join("/"))
+}
+
+/// An entry that links to another one
+#[derive(Debug, Serialize)]
+struct Backlink<'a> {
+    title: &'a str,
+    url: &'a str,
+}
+
+/// An entry's version in another language
+#[derive(Debug, Serialize)]
+struct Translation<'a> {
+    language: &'a str,
+    title: &'a str,
+    url: &'a str,
+}
+
+/// A standalone page's version in one of the `languages`
+#[derive(Debug, Serialize)]
+struct LanguageLink<'a> {
+    code: &'a str,
+    url: String,
+}
+
+/// What an entry's template knows about the series it's part of
+#[derive(Debug, Serialize)]
+struct SeriesContext<'a> {
+    name: &'a str,
+    parts: Vec<&'a Metadata>,
+    /// 1-based position of the entry being rendered in `parts`
+    position: usize,
+    total: usize,
+}
+
+/// Source, output directory, widths, and formats of a processed image
+type ImageCacheKey = (PathBuf, PathBuf, Vec<u32>, Vec<ImageFormat>);
+
+/// State shared while building a site
+struct Build<'a> {
+    config: &'a Config,
+    template_handle: &'a TemplateHandle,
+    transforms: &'a [Box<Transform>],
+    cmark_opts: pulldown_cmark::Options,
+    report: BuildReport,
+    search_index: Vec<SearchIndexEntry>,
+    /// URL of every alias, and the URL it redirects to
+    redirects: Vec<(String, String)>,
+    /// `srcset`s of images that were already processed, by their source,
+    /// output directory, widths, and formats. `None` for images that had no
+    /// variants, e.g. ones smaller than every width.
+    #[cfg_attr(not(feature = "images"), allow(dead_code))]
+    image_cache: BTreeMap<ImageCacheKey, Option<BTreeMap<ImageFormat, String>>>,
+    /// What entries' `created_at` is compared with to tell if they're in the
+    /// future
+    started_at: OffsetDateTime,
+    /// Kept whether or not `profile` is enabled, since timing is cheap
+    profile: BuildProfile,
+}
+
+impl Build<'_> {
+    /// Passes on the result of building the entry at `path`, unless it
+    /// failed and `keep_going` is enabled, in which case the error goes in
+    /// the build report instead.
+    fn keep_going<T>(
+        &mut self,
+        path: &Path,
+        result: color_eyre::Result<T>,
+    ) -> color_eyre::Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.config.keep_going => {
+                self.report.errors.push(EntryError {
+                    path: path.to_path_buf(),
+                    message: format!("{:#}", e),
+                });
+
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether an entry is built at all. Drafts, and entries created after
+    /// the build started, are skipped unless `drafts` and `future` are
+    /// enabled. Entries that aren't are in neither `dest_dir` nor any
+    /// listing.
+    fn is_published(&self, metadata: &Metadata) -> bool {
+        let is_future = metadata
+            .created_at
+            .is_some_and(|created_at| created_at > self.started_at);
+
+        (!metadata.draft || self.config.drafts) && (!is_future || self.config.future)
+    }
+
+    /// Reads a markdown or HTML file and its metadata as an entry of `page`.
+    /// Entries that aren't published are skipped.
+    fn read_markdown_file<'a>(
+        &mut self,
+        page: &'a PageEntries,
+        language: Option<&'a Language>,
+        file: PathBuf,
+        template_path: Option<&'a Path>,
+        defaults: Option<&serde_yml::Mapping>,
+    ) -> color_eyre::Result<Option<SourceEntry<'a>>> {
+        let started = Instant::now();
+        let markdown = std::fs::read_to_string(file.as_path())?;
+
+        // Only the front matter is needed until the entry is rendered
+        let metadata = front_matter(markdown.as_str())
+            .map(|front_matter| deserialize_metadata(front_matter, file.as_path(), defaults))
+            .transpose()?;
+
+        let md_file_name = file.file_stem().ok_or(ParseFilesError::FileName)?;
+
+        let slug = if self.config.ascii_slugs {
+            slugify(md_file_name.to_string_lossy().as_ref(), true)
+        } else {
+            md_file_name.to_string_lossy().to_string()
+        };
+
+        let metadata = metadata.ok_or(color_eyre::Report::msg(format!(
+            "failed to extract metadata from file {:?}",
+            file
+        )))?;
+
+        if !self.is_published(&metadata) {
+            return Ok(None);
+        }
+
+        if metadata
+            .cover
+            .as_ref()
+            .is_some_and(|cover| cover.alt.is_none())
+        {
+            self.report
+                .warnings
+                .push(BuildWarning::MissingCoverAlt(file.clone()));
+        }
+
+        if let (Some(summary), Some(max_length)) =
+            (metadata.summary.as_deref(), self.config.summary_max_length)
         {
-            std::fs::create_dir_all(target_file.parent().ok_or(ParseFilesError::Parent)?)?;
+            let length = char_ends(summary).len();
+
+            if length > max_length {
+                self.report.warnings.push(BuildWarning::LongSummary(
+                    file.clone(),
+                    length,
+                    max_length,
+                ));
+            }
+        }
+
+        let entry_path = match page.permalink {
+            Some(ref permalink) => expand_permalink(
+                permalink.as_str(),
+                slug.as_str(),
+                &metadata,
+                self.config.ascii_slugs,
+            )
+            .map_err(|e| ParseFilesError::Permalink(file.clone(), e))?,
+            None => slug.clone(),
+        };
+
+        let entry_path = language_path(language, format!("{}/{}", page.dir(), entry_path).as_str());
+
+        let scan = if self.config.is_html_entry(file.as_path()) {
+            let text = strip_tags(without_front_matter(markdown.as_str()));
+
+            MarkdownScan {
+                text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                has_math: false,
+                has_code: false,
+                references: html_references(without_front_matter(markdown.as_str())),
+            }
+        } else {
+            scan_markdown(markdown.as_str(), self.cmark_opts_for(&metadata))
+        };
+
+        if self.config.builds_page(page.name.as_str()) {
+            let cwd = std::env::current_dir()?;
+
+            for reference in scan.references.iter() {
+                if is_local_file(reference, file.as_path(), cwd.as_path()) {
+                    self.report
+                        .warnings
+                        .push(BuildWarning::LocalFile(file.clone(), reference.clone()));
+                }
+            }
+        }
+
+        let metadata = Metadata {
+            url: self.config.url(entry_path.as_str()),
+            plain_text: scan.text,
+            has_math: metadata.has_math || scan.has_math,
+            has_code: metadata.has_code || scan.has_code,
+            file_name: slug,
+            language: language.map(|language| language.code.clone()),
+            ..metadata
+        };
+
+        self.profile.read += started.elapsed();
+
+        let metadata = match (&metadata.cover, &self.config.images) {
+            (Some(cover), Some(images)) if self.config.builds_page(page.name.as_str()) => {
+                let started = Instant::now();
+                let cover_srcset = self.process_image(
+                    cover.src.as_str(),
+                    file.as_path(),
+                    entry_path.as_str(),
+                    images.widths.as_slice(),
+                    images.formats.as_slice(),
+                )?;
+                self.profile.images += started.elapsed();
+
+                Metadata {
+                    cover_srcset,
+                    ..metadata
+                }
+            }
+            _ => metadata,
+        };
+
+        Ok(Some(SourceEntry {
+            page,
+            language,
+            path: file,
+            dir: PathBuf::from(entry_path),
+            template_path,
+            markdown,
+            metadata,
+            asset_dir: None,
+            assets: Vec::new(),
+            links: scan.references,
+        }))
+    }
+
+    /// Links from the root of the site, or of the entry's language, down to
+    /// the entry itself. Every directory above the entry gets one, named
+    /// after the directory, except for the root, which has no name.
+    fn breadcrumbs<'a>(&self, source: &'a SourceEntry) -> Vec<Breadcrumb<'a>> {
+        let root = language_path(source.language, "");
+        let mut path = root.trim_end_matches('/').to_string();
+
+        let mut breadcrumbs = vec![Breadcrumb {
+            name: "",
+            url: self.config.url(path.as_str()),
+        }];
+
+        let segments: Vec<&str> = source
+            .dir
+            .strip_prefix(root.as_str())
+            .unwrap_or(source.dir.as_path())
+            .iter()
+            .filter_map(|segment| segment.to_str())
+            .collect();
+
+        for (i, segment) in segments.iter().enumerate() {
+            path = format!("{}/{}", path, segment);
+
+            breadcrumbs.push(Breadcrumb {
+                name: if i == segments.len() - 1 {
+                    source.metadata.title.as_str()
+                } else {
+                    segment
+                },
+                url: self.config.url(path.as_str()),
+            });
+        }
+
+        breadcrumbs
+    }
+
+    /// `build_time` and `current_year` of every template, taken from when the
+    /// build started, so that every page of a build agrees on them even when
+    /// its template handle is reused across builds
+    fn time_context(&self) -> Value {
+        minijinja::context!(
+            build_time => self
+                .started_at
+                .format(&time::format_description::well_known::Rfc3339)
+                .map(Value::from)
+                .unwrap_or_default(),
+            current_year => self.started_at.year(),
+        )
+    }
+
+    /// Markdown options an entry is rendered with, which depend on its front
+    /// matter. Reading the front matter itself doesn't depend on any of them.
+    fn cmark_opts_for(&self, metadata: &Metadata) -> pulldown_cmark::Options {
+        let mut cmark_opts = self.cmark_opts;
+
+        if metadata.smart_punctuation == Some(false) {
+            cmark_opts.remove(pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION);
+        }
+
+        cmark_opts
+    }
+
+    /// Renders an entry, along with the redirect pages of its aliases and its
+    /// assets. `context` is available to the entry's template on top of its
+    /// metadata.
+    fn render_source_entry(
+        &mut self,
+        source: &SourceEntry,
+        context: Value,
+    ) -> color_eyre::Result<()> {
+        let SourceEntry {
+            page,
+            path: file,
+            markdown,
+            metadata,
+            ..
+        } = source;
+
+        let cmark_opts = self.cmark_opts_for(metadata);
+        let (html_partial, headings) = if self.config.is_html_entry(file) {
+            (
+                without_front_matter(markdown.as_str()).to_string(),
+                Vec::new(),
+            )
+        } else {
+            let srcsets = self.content_srcsets(
+                markdown.as_str(),
+                cmark_opts,
+                file,
+                source.dir.to_string_lossy().as_ref(),
+            )?;
+            let started = Instant::now();
+            let parsed = parse_markdown(
+                markdown.as_str(),
+                cmark_opts,
+                self.config,
+                &srcsets,
+                self.transforms,
+            );
+            self.profile.parse += started.elapsed();
+
+            parsed
+        };
+        let template_path = metadata.layout.as_deref().or(source.template_path);
+
+        let source_path = self
+            .config
+            .content_root
+            .as_deref()
+            .and_then(|content_root| file.strip_prefix(content_root).ok())
+            .unwrap_or(file);
+
+        let context = minijinja::context!(
+            source_path => source_path.to_string_lossy().replace('\\', "/"),
+            base_path => self.config.base_path(),
+            breadcrumbs => self.breadcrumbs(source),
+            headings_json => headings_json(headings.as_slice()),
+            ..context
+        );
+
+        let started = Instant::now();
+        let html = render_entry(
+            html_partial,
+            metadata,
+            context,
+            file,
+            template_path,
+            self.template_handle,
+        )?;
+        self.profile.render += started.elapsed();
+
+        let html = if metadata.draft && self.config.draft_banner {
+            inject_draft_banner(html.as_str())
+        } else {
+            html
+        };
+
+        let entry_dir = self.config.dest_dir.join(source.dir.as_path());
+
+        let target_file = entry_dir.join("index").with_extension("html");
+
+        self.write_output(
+            target_file.as_path(),
+            html.as_bytes(),
+            Some(file),
+            Some(metadata),
+        )?;
+
+        if self.config.search_index && metadata.is_listed() {
+            self.search_index.push(SearchIndexEntry {
+                url: metadata.url.clone(),
+                title: metadata.title.clone(),
+                tags: metadata.tags.clone(),
+                summary: metadata.summary.clone(),
+                body: metadata.plain_text.clone(),
+            });
+        }
+
+        if let Some(ref aliases) = metadata.aliases {
+            let mut written = HashSet::new();
+
+            // Listing an alias twice is harmless, but it's only written once
+            for alias in aliases
+                .iter()
+                .filter(|alias| written.insert(alias.as_path()))
+            {
+                if self.config.redirect_style != RedirectStyle::Html {
+                    let alias_path = format!("{}/{}", page.dir(), alias.to_string_lossy());
+                    let alias_path = language_path(source.language, alias_path.as_str());
+
+                    self.redirects
+                        .push((self.config.url(alias_path.as_str()), metadata.url.clone()));
+                }
+
+                if self.config.redirect_style == RedirectStyle::Netlify {
+                    continue;
+                }
+
+                let alias_file = self
+                    .config
+                    .dest_dir
+                    .join(language_path(source.language, page.dir()))
+                    .join(alias)
+                    .join("index")
+                    .with_extension("html");
+
+                let redir_html = format!(
+                    "<!DOCTYPE html>
+<html>
+  <head>
+    <title>{0}</title>
+    <link rel=\"canonical\" href=\"{1}\"/>
+    <meta http-equiv=\"content-type\" content=\"text/html; charset=utf-8\"/>
+    <meta http-equiv=\"refresh\" content=\"0; url={1}\"/>
+  </head>
+  <body>
+    If you aren't redirected, you can manually click this link:
+    <a href=\"{1}\">{1}</a>.
+  </body>
+</html>",
+                    metadata.title, metadata.url,
+                );
+
+                self.write_output(
+                    alias_file.as_path(),
+                    redir_html.as_bytes(),
+                    Some(file),
+                    None,
+                )?;
+            }
+        }
+
+        if let Some(ref asset_dir) = source.asset_dir {
+            for asset in source.assets.iter() {
+                let source_file = asset_dir.join(asset);
+                let contents = std::fs::read(source_file.as_path())?;
+
+                self.write_output(
+                    entry_dir.join(asset).as_path(),
+                    contents.as_slice(),
+                    Some(source_file.as_path()),
+                    None,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `srcset` of every local image in an entry's markdown, by its `src`,
+    /// when `images.responsive` is configured. Images are resized in their
+    /// own format, so that an `<img>` with them works wherever the original
+    /// would. Images in other formats, like SVG or GIF, are left alone.
+    fn content_srcsets(
+        &mut self,
+        markdown: &str,
+        opts: pulldown_cmark::Options,
+        markdown_file: &Path,
+        entry_path: &str,
+    ) -> color_eyre::Result<HashMap<String, String>> {
+        let config = self.config;
+        let mut srcsets = HashMap::new();
+
+        let Some(responsive) = config
+            .images
+            .as_ref()
+            .and_then(|images| images.responsive.as_ref())
+        else {
+            return Ok(srcsets);
+        };
+
+        for event in Parser::new_ext(markdown, opts) {
+            let Event::Start(Tag::Image { dest_url, .. }) = event else {
+                continue;
+            };
+
+            if srcsets.contains_key(dest_url.as_ref()) || images::is_remote(dest_url.as_ref()) {
+                continue;
+            }
+
+            let Some(format) = ImageFormat::from_path(dest_url.as_ref()) else {
+                continue;
+            };
+
+            let started = Instant::now();
+            let srcset = self.process_image(
+                dest_url.as_ref(),
+                markdown_file,
+                entry_path,
+                responsive.widths.as_slice(),
+                &[format],
+            )?;
+            self.profile.images += started.elapsed();
+
+            if let Some(srcset) = srcset.and_then(|mut srcset| srcset.remove(&format)) {
+                srcsets.insert(dest_url.to_string(), srcset);
+            }
+        }
+
+        Ok(srcsets)
+    }
+
+    /// Generates variants of a local image referenced by an entry as `src`,
+    /// in every one of `widths` and `formats`, and returns their `srcset` for
+    /// each format. The variants are written next to where the image itself
+    /// is served from.
+    #[cfg(feature = "images")]
+    fn process_image(
+        &mut self,
+        src: &str,
+        markdown_file: &Path,
+        entry_path: &str,
+        widths: &[u32],
+        formats: &[ImageFormat],
+    ) -> color_eyre::Result<Option<BTreeMap<ImageFormat, String>>> {
+        let Some(images_config) = self.config.images.as_ref() else {
+            return Ok(None);
+        };
+
+        if images::is_remote(src) {
+            return Ok(None);
+        }
+
+        // Site-absolute paths are read from the images' source directory,
+        // and relative ones from the entry's
+        let (source, output_dir) = match src.strip_prefix('/') {
+            Some(src) => (
+                images_config.source_dir.join(src),
+                Path::new(src)
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .to_path_buf(),
+            ),
+            None => (
+                markdown_file.parent().unwrap_or(Path::new("")).join(src),
+                Path::new(entry_path).join(Path::new(src).parent().unwrap_or(Path::new(""))),
+            ),
+        };
+
+        if !source.is_file() {
+            self.report.warnings.push(BuildWarning::MissingImage(
+                markdown_file.to_path_buf(),
+                src.to_string(),
+            ));
+
+            return Ok(None);
+        }
+
+        let cache_key = (
+            source.clone(),
+            output_dir.clone(),
+            widths.to_vec(),
+            formats.to_vec(),
+        );
+
+        if let Some(srcset) = self.image_cache.get(&cache_key) {
+            return Ok(srcset.clone());
+        }
+
+        let mut sources: BTreeMap<ImageFormat, Vec<String>> = BTreeMap::new();
+
+        for variant in images::resize(source.as_path(), widths, formats)? {
+            let path = output_dir.join(variant.file_name.as_str());
+            let url = self.config.file_url(path.to_string_lossy().as_ref());
+
+            self.write_output(
+                self.config.dest_dir.join(path).as_path(),
+                variant.contents.as_slice(),
+                Some(source.as_path()),
+                None,
+            )?;
+
+            sources
+                .entry(variant.format)
+                .or_default()
+                .push(format!("{} {}w", url, variant.width));
         }
 
-        let _ = std::fs::write(target_file, html);
+        let srcset: BTreeMap<ImageFormat, String> = sources
+            .into_iter()
+            .map(|(format, sources)| (format, sources.join(", ")))
+            .collect();
+        let srcset = Some(srcset).filter(|srcset| !srcset.is_empty());
+
+        self.image_cache.insert(cache_key, srcset.clone());
+
+        Ok(srcset)
     }
 
-    Ok(())
+    #[cfg(not(feature = "images"))]
+    fn process_image(
+        &mut self,
+        _src: &str,
+        _markdown_file: &Path,
+        _entry_path: &str,
+        _widths: &[u32],
+        _formats: &[ImageFormat],
+    ) -> color_eyre::Result<Option<BTreeMap<ImageFormat, String>>> {
+        let warned = self
+            .report
+            .warnings
+            .iter()
+            .any(|warning| matches!(warning, BuildWarning::ImagesDisabled));
+
+        if self.config.images.is_some() && !warned {
+            self.report.warnings.push(BuildWarning::ImagesDisabled);
+        }
+
+        Ok(None)
+    }
+
+    /// Writes `contents` to `target_file`, creating its parent directories
+    /// if needed, and records it in the build report.
+    fn write_output(
+        &mut self,
+        target_file: &Path,
+        contents: &[u8],
+        source: Option<&Path>,
+        metadata: Option<&Metadata>,
+    ) -> color_eyre::Result<()> {
+        let started = Instant::now();
+        let normalized;
+        let contents = match std::str::from_utf8(contents) {
+            Ok(html)
+                if self.config.normalize_html
+                    && target_file.extension().is_some_and(|ext| ext == "html") =>
+            {
+                normalized = normalize_html(html);
+                normalized.as_bytes()
+            }
+            _ => contents,
+        };
+
+        let change = match std::fs::read(target_file) {
+            Ok(existing) if existing == contents => FileChange::Unchanged,
+            Ok(_) => FileChange::Updated,
+            Err(_) => FileChange::Created,
+        };
+
+        // Left alone when unchanged, so that its modification time still says
+        // when it last changed
+        if !self.config.dry_run && change != FileChange::Unchanged {
+            let parent = target_file.parent().ok_or(ParseFilesError::Parent)?;
+
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            std::fs::write(target_file, contents)?;
+        }
+
+        let hash = Sha256::digest(contents)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        self.report.files.push(OutputFile {
+            path: target_file
+                .strip_prefix(self.config.dest_dir.as_path())
+                .unwrap_or(target_file)
+                .to_path_buf(),
+            source: source.map(Path::to_path_buf),
+            hash,
+            size: contents.len(),
+            change,
+            entry: metadata.map(OutputEntry::from),
+        });
+
+        self.profile.write += started.elapsed();
+
+        Ok(())
+    }
+}
+
+/// URL of the asset at `path`, relative to `hashed_assets_dir`, with a hash
+/// of its contents in its file name, e.g. `/css/style.1a2b3c4d.css`. The
+/// asset is recorded in `hashed_assets` for the build to copy it over.
+fn asset_url(
+    config: &Config,
+    hashed_assets: &Mutex<BTreeMap<PathBuf, PathBuf>>,
+    path: &str,
+) -> Result<String, minijinja::Error> {
+    let path = path.trim_start_matches('/');
+
+    let Some(ref assets_dir) = config.hashed_assets_dir else {
+        return Ok(config.file_url(path));
+    };
+
+    let relative = Path::new(path);
+
+    if escapes_dest_dir(relative) {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("asset {:?} is outside of {:?}", path, assets_dir),
+        ));
+    }
+
+    let source = assets_dir.join(relative);
+    let contents = std::fs::read(source.as_path()).map_err(|e| {
+        minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("failed to read asset {:?}", source),
+        )
+        .with_source(e)
+    })?;
+
+    let hash: String = Sha256::digest(contents.as_slice())
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let file_name = match (relative.file_stem(), relative.extension()) {
+        (Some(stem), Some(extension)) => format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            hash,
+            extension.to_string_lossy()
+        ),
+        _ => format!("{}.{}", path.rsplit('/').next().unwrap_or(path), hash),
+    };
+
+    let hashed = relative.with_file_name(file_name);
+    let url = config.file_url(hashed.to_string_lossy().as_ref());
+
+    hashed_assets
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(hashed, source);
+
+    Ok(url)
 }
 
 fn published_on(state: &State, value: Value, kwargs: Kwargs) -> Result<String, minijinja::Error> {
@@ -438,3 +4084,1179 @@ fn published_on(state: &State, value: Value, kwargs: Kwargs) -> Result<String, m
         date_str, user_date_str
     ))
 }
+
+/// Average reading speed used by `reading_time` when no `wpm` is given.
+const DEFAULT_WORDS_PER_MINUTE: usize = 200;
+
+/// Counts the words in `input`, ignoring anything inside HTML tags so that it
+/// works on both raw text and rendered content.
+fn count_words(input: &str) -> usize {
+    strip_tags(input).split_whitespace().count()
+}
+
+/// `input` with everything inside HTML tags left out. Every tag is replaced
+/// with a space, so that the words around it stay apart.
+fn strip_tags(input: &str) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut in_tag = false;
+
+    for c in input.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// Values of the `href` and `src` attributes in `input`, an HTML entry, for
+/// what `scan_markdown` gets from links and images
+fn html_references(input: &str) -> Vec<String> {
+    let mut references = Vec::new();
+
+    for tag in input.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+
+        for (at, _) in tag.match_indices(char::is_whitespace) {
+            let attribute = &tag[at + 1..];
+            let Some(value) = ["href=", "src="]
+                .iter()
+                .find_map(|name| attribute.strip_prefix(name))
+            else {
+                continue;
+            };
+            let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+                continue;
+            };
+
+            if let Some((value, _)) = value[1..].split_once(quote) {
+                references.push(value.to_string());
+            }
+        }
+    }
+
+    references
+}
+
+fn word_count(value: &str) -> usize {
+    count_words(value)
+}
+
+fn truncate_filter(value: &str, length: usize, kwargs: Kwargs) -> Result<String, minijinja::Error> {
+    let words: Option<bool> = kwargs.get("words")?;
+    kwargs.assert_all_used()?;
+
+    Ok(truncate(value, length, words.unwrap_or_default()))
+}
+
+fn reading_time(value: &str, kwargs: Kwargs) -> Result<usize, minijinja::Error> {
+    let wpm: Option<usize> = kwargs.get("wpm")?;
+    kwargs.assert_all_used()?;
+
+    let wpm = wpm.unwrap_or(DEFAULT_WORDS_PER_MINUTE);
+
+    if wpm == 0 {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "wpm must be greater than 0",
+        ));
+    }
+
+    Ok(count_words(value).div_ceil(wpm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A site written to its own temporary directory, which is removed once
+    /// the site is dropped
+    struct TestSite {
+        dir: PathBuf,
+    }
+
+    impl TestSite {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("puggle-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(dir.as_path());
+
+            for (path, contents) in files {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, contents).unwrap();
+            }
+
+            Self { dir }
+        }
+
+        /// Config of the site, where `pages` is YAML for its pages, with
+        /// `{dir}` standing for the site's directory
+        fn config(&self, pages: &str) -> Config {
+            let yaml = format!(
+                "templates_dir: {dir}/templates\ndest_dir: {dir}/dist\n{}",
+                pages.replace("{dir}", self.dir.to_str().unwrap()),
+                dir = self.dir.display(),
+            );
+
+            serde_yml::from_str(yaml.as_str()).unwrap()
+        }
+
+        fn build(&self, config: Config) -> BuildReport {
+            build_from_dir(config).unwrap()
+        }
+
+        /// Contents of `path` in the site's `dest_dir`
+        fn output(&self, path: &str) -> String {
+            std::fs::read_to_string(self.dir.join("dist").join(path)).unwrap()
+        }
+
+        /// Contents of every file in the site's `dest_dir`, by their path in it
+        fn outputs(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+            fn walk(dir: &Path, root: &Path, outputs: &mut BTreeMap<PathBuf, Vec<u8>>) {
+                for entry in std::fs::read_dir(dir).unwrap() {
+                    let path = entry.unwrap().path();
+
+                    if path.is_dir() {
+                        walk(path.as_path(), root, outputs);
+                    } else {
+                        let contents = std::fs::read(path.as_path()).unwrap();
+                        outputs.insert(path.strip_prefix(root).unwrap().to_path_buf(), contents);
+                    }
+                }
+            }
+
+            let dest_dir = self.dir.join("dist");
+            let mut outputs = BTreeMap::new();
+            walk(dest_dir.as_path(), dest_dir.as_path(), &mut outputs);
+            outputs
+        }
+    }
+
+    impl Drop for TestSite {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(self.dir.as_path());
+        }
+    }
+
+    /// An entry with `front_matter`, plus whichever of the required keys it
+    /// leaves out
+    fn entry(front_matter: &str, body: &str) -> String {
+        let mut yaml = front_matter.to_string();
+
+        for required in ["title: Entry", "created_at:", "updated_at:", "tags: []"] {
+            let key = &required[..=required.find(':').unwrap()];
+
+            if !front_matter.lines().any(|line| line.starts_with(key)) {
+                yaml.push('\n');
+                yaml.push_str(required);
+            }
+        }
+
+        format!("---\n{}\n---\n\n{}\n", yaml.trim_start(), body)
+    }
+
+    /// Config without any pages, with `yaml` on top
+    fn config(yaml: &str) -> Config {
+        serde_yml::from_str(format!("templates_dir: templates\ndest_dir: dist\n{}", yaml).as_str())
+            .unwrap()
+    }
+
+    fn render(markdown: &str, config: &Config) -> String {
+        parse(markdown, pulldown_cmark::Options::all(), config)
+    }
+
+    #[test]
+    fn entries_are_ordered_by_weight_then_creation_date() {
+        let site = TestSite::new(
+            "order",
+            &[
+                (
+                    "templates/list.html",
+                    "{% for e in pages.blog %}{{ e.file_name }} {% endfor %}",
+                ),
+                ("templates/entry.html", ""),
+                ("posts/a-undated.md", entry("title: A", "").as_str()),
+                (
+                    "posts/b-new.md",
+                    entry("created_at: 2024-03-01", "").as_str(),
+                ),
+                ("posts/c-heavy.md", entry("weight: 20", "").as_str()),
+                (
+                    "posts/d-old.md",
+                    entry("created_at: 2024-01-01", "").as_str(),
+                ),
+                (
+                    "posts/e-light.md",
+                    entry("weight: 10\ncreated_at: 2024-05-01", "").as_str(),
+                ),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(
+            site.output("blog/index.html"),
+            "e-light c-heavy d-old b-new a-undated "
+        );
+    }
+
+    #[test]
+    fn admonitions() {
+        let config = config("");
+
+        assert_eq!(
+            render(":::note\nSome *text*.\n:::", &config),
+            "<div class=\"admonition note\">\n<p>Some <em>text</em>.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render(":::note Heads up\nText.\n:::", &config),
+            "<div class=\"admonition note\">\n<p class=\"admonition-title\">Heads up</p>\n\
+                <p>Text.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render("::: warning\nText.\n:::", &config),
+            "<div class=\"admonition warning\">\n<p>Text.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render(":::Warning Mind the <gap>\nText.\n:::", &config),
+            "<div class=\"admonition warning\">\n\
+                <p class=\"admonition-title\">Mind the &lt;gap&gt;</p>\n<p>Text.</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn admonitions_with_unknown_names_have_no_class_of_their_own() {
+        assert_eq!(
+            render(":::spoiler Ending\nText.\n:::", &config("")),
+            "<div class=\"admonition\">\n<p class=\"admonition-title\">Ending</p>\n\
+                <p>Text.</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn nested_and_unclosed_admonitions() {
+        let config = config("");
+
+        assert_eq!(
+            render(
+                "::::tip\nOuter.\n:::note\nInner.\n:::\nOuter again.\n::::",
+                &config
+            ),
+            "<div class=\"admonition tip\">\n<p>Outer.</p>\n\
+                <div class=\"admonition note\">\n<p>Inner.</p>\n</div>\n\
+                <p>Outer again.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render(":::danger\nNever closed.", &config),
+            "<div class=\"admonition danger\">\n<p>Never closed.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render("```\n:::note\n```", &config),
+            "<pre><code>:::note\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn heading_offset_shifts_every_level_and_stops_at_h6() {
+        let markdown = "# One\n\n## Two\n\n### Three\n\n#### Four\n\n##### Five\n\n###### Six";
+
+        assert_eq!(
+            render(markdown, &config("heading_offset: 1")),
+            "<h2 id=\"one\">One</h2>\n<h3 id=\"two\">Two</h3>\n<h4 id=\"three\">Three</h4>\n\
+                <h5 id=\"four\">Four</h5>\n<h6 id=\"five\">Five</h6>\n<h6 id=\"six\">Six</h6>\n"
+        );
+        assert_eq!(
+            render(markdown, &config("heading_offset: 10")),
+            "<h6 id=\"one\">One</h6>\n<h6 id=\"two\">Two</h6>\n<h6 id=\"three\">Three</h6>\n\
+                <h6 id=\"four\">Four</h6>\n<h6 id=\"five\">Five</h6>\n<h6 id=\"six\">Six</h6>\n"
+        );
+        assert_eq!(
+            render(markdown, &config("")),
+            "<h1 id=\"one\">One</h1>\n<h2 id=\"two\">Two</h2>\n<h3 id=\"three\">Three</h3>\n\
+                <h4 id=\"four\">Four</h4>\n<h5 id=\"five\">Five</h5>\n<h6 id=\"six\">Six</h6>\n"
+        );
+    }
+
+    #[test]
+    fn building_twice_writes_the_same_files() {
+        let site = TestSite::new(
+            "deterministic",
+            &[
+                (
+                    "templates/list.html",
+                    "{% for e in pages.blog %}{{ e.title }}{% endfor %}\
+                        {% for tag, tagged in tags|items %}{{ tag }}: {{ tagged.count }}{% endfor %}",
+                ),
+                ("templates/entry.html", "{{ metadata.title }}"),
+                ("posts/a.md", entry("title: A\ntags: [x, y]\naliases: [old-a]", "A").as_str()),
+                ("posts/b.md", entry("title: B\ntags: [y, z]\naliases: [old-b]", "B").as_str()),
+                ("posts/c.md", entry("title: C\ntags: [z, x]", "C").as_str()),
+            ],
+        );
+        let config = site.config(
+            "manifest: true
+search_index: true
+redirect_style: both
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config.clone());
+        let first = site.outputs();
+
+        std::fs::remove_dir_all(site.dir.join("dist")).unwrap();
+        site.build(config);
+
+        assert_eq!(first, site.outputs());
+    }
+
+    #[test]
+    fn url_trailing_slash_policies() {
+        let always = config("");
+        let never = config("url_trailing_slash: never");
+        let index_html = config("url_trailing_slash: index_html");
+
+        assert_eq!(always.url("blog/post"), "/blog/post/");
+        assert_eq!(never.url("blog/post"), "/blog/post");
+        assert_eq!(index_html.url("blog/post"), "/blog/post/index.html");
+
+        assert_eq!(always.url(""), "/");
+        assert_eq!(never.url(""), "/");
+        assert_eq!(index_html.url(""), "/index.html");
+
+        // Files are linked to as they are under every policy
+        for config in [always, never, index_html] {
+            assert_eq!(config.file_url("blog/feed.xml"), "/blog/feed.xml");
+        }
+    }
+
+    #[test]
+    fn entry_urls_follow_url_trailing_slash() {
+        let site = TestSite::new(
+            "trailing-slash",
+            &[
+                (
+                    "templates/list.html",
+                    "{% for e in pages.blog %}{{ e.url|safe }}{% endfor %}",
+                ),
+                ("templates/entry.html", ""),
+                ("posts/post.md", entry("", "").as_str()),
+            ],
+        );
+        let pages = "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+";
+
+        for (policy, url) in [
+            ("always", "/blog/post/"),
+            ("never", "/blog/post"),
+            ("index_html", "/blog/post/index.html"),
+        ] {
+            let config = site.config(format!("url_trailing_slash: {}\n{}", policy, pages).as_str());
+            site.build(config);
+
+            assert_eq!(site.output("blog/index.html"), url);
+        }
+    }
+
+    #[test]
+    fn slugs_of_non_ascii_text() {
+        assert_eq!(slugify("Café au lait", false), "café-au-lait");
+        assert_eq!(slugify("Café au lait", true), "cafe-au-lait");
+        assert_eq!(slugify("Привет мир", false), "привет-мир");
+        assert_eq!(slugify("Привет мир", true), "privet-mir");
+        assert_eq!(slugify("你好世界", false), "你好世界");
+        assert_eq!(slugify("你好世界", true), "ni-hao-shi-jie");
+    }
+
+    #[test]
+    fn heading_anchors_follow_ascii_slugs() {
+        let markdown = "# Café au lait\n\n# Привет мир\n\n# 你好世界";
+
+        assert_eq!(
+            render(markdown, &config("ascii_slugs: true")),
+            "<h1 id=\"cafe-au-lait\">Café au lait</h1>\n<h1 id=\"privet-mir\">Привет мир</h1>\n\
+                <h1 id=\"ni-hao-shi-jie\">你好世界</h1>\n"
+        );
+        assert_eq!(
+            render(markdown, &config("")),
+            "<h1 id=\"café-au-lait\">Café au lait</h1>\n<h1 id=\"привет-мир\">Привет мир</h1>\n\
+                <h1 id=\"你好世界\">你好世界</h1>\n"
+        );
+    }
+
+    #[test]
+    fn entry_slugs_follow_ascii_slugs() {
+        let site = TestSite::new(
+            "ascii-slugs",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", "{{ metadata.title }}"),
+                ("posts/Café au lait.md", entry("title: Café", "").as_str()),
+                ("posts/Привет мир.md", entry("title: Привет", "").as_str()),
+            ],
+        );
+        let config = site.config(
+            "ascii_slugs: true
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(site.output("blog/cafe-au-lait/index.html"), "Café");
+        assert_eq!(site.output("blog/privet-mir/index.html"), "Привет");
+    }
+
+    #[test]
+    fn base_path_prefixes_urls_without_double_slashes() {
+        for base_path in ["/docs", "docs", "/docs/", "docs/"] {
+            let config = config(format!("base_path: \"{}\"", base_path).as_str());
+
+            assert_eq!(config.base_path(), "/docs");
+            assert_eq!(config.url(""), "/docs/");
+            assert_eq!(config.url("blog/post"), "/docs/blog/post/");
+            assert_eq!(config.url("/blog/post/"), "/docs/blog/post/");
+            assert_eq!(config.file_url("feed.xml"), "/docs/feed.xml");
+        }
+
+        for base_path in ["\"\"", "/"] {
+            let config = config(format!("base_path: {}", base_path).as_str());
+
+            assert_eq!(config.base_path(), "");
+            assert_eq!(config.url(""), "/");
+            assert_eq!(config.url("blog/post"), "/blog/post/");
+            assert_eq!(config.file_url("feed.xml"), "/feed.xml");
+        }
+    }
+
+    #[test]
+    fn entry_urls_are_under_base_path() {
+        let site = TestSite::new(
+            "base-path",
+            &[
+                (
+                    "templates/list.html",
+                    "{% for e in pages.blog %}{{ e.url|safe }}{% endfor %}",
+                ),
+                ("templates/entry.html", ""),
+                ("posts/post.md", entry("", "").as_str()),
+            ],
+        );
+        let config = site.config(
+            "base_path: /docs/
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        // Still written to the same place
+        assert_eq!(site.output("blog/index.html"), "/docs/blog/post/");
+    }
+
+    #[test]
+    fn a_page_named_index_is_written_to_the_root() {
+        let site = TestSite::new(
+            "homepage",
+            &[
+                ("templates/home.html", "home"),
+                ("templates/about.html", "about"),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: index
+    template_path: home.html
+  - name: about
+    template_path: about.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(site.output("index.html"), "home");
+        assert_eq!(site.output("about/index.html"), "about");
+        assert!(!site.dir.join("dist/index/index.html").exists());
+    }
+
+    fn created_at(date: &str) -> Result<Option<OffsetDateTime>, serde_yml::Error> {
+        let yaml = format!("title: A\ntags: []\nupdated_at:\ncreated_at: {}", date);
+        serde_yml::from_str::<Metadata>(yaml.as_str()).map(|metadata| metadata.created_at)
+    }
+
+    fn rfc3339(date: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(date, &time::format_description::well_known::Rfc3339).unwrap()
+    }
+
+    #[test]
+    fn date_only_dates_are_midnight_utc() {
+        assert_eq!(
+            created_at("2021-03-01").unwrap(),
+            Some(rfc3339("2021-03-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn datetimes_keep_their_time_and_offset() {
+        assert_eq!(
+            created_at("2024-06-29T17:29:00Z").unwrap(),
+            Some(rfc3339("2024-06-29T17:29:00Z"))
+        );
+        let with_offset = created_at("2024-06-29T17:29:00+08:00").unwrap().unwrap();
+        assert_eq!(with_offset, rfc3339("2024-06-29T17:29:00+08:00"));
+        assert_eq!(with_offset.offset().whole_hours(), 8);
+        assert_eq!(created_at("").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for date in ["2021-13-01", "March 1st", "2021-03-01T25:00:00Z"] {
+            let error = created_at(date).unwrap_err().to_string();
+            assert!(error.contains("invalid date"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn code_block_titles() {
+        let config = config("");
+
+        assert_eq!(
+            render("```rust title=\"src/main.rs\"\nfn main() {}\n```", &config),
+            "<div class=\"code-title\">src/main.rs</div>\n\
+                <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+        assert_eq!(
+            render(
+                "```rust filename=\"<lib>.rs\" hl_lines=\"1\"\nfn main() {}\n```",
+                &config
+            ),
+            "<div class=\"code-title\">&lt;lib&gt;.rs</div>\n\
+                <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+        assert_eq!(
+            render("```rust\nfn main() {}\n```", &config),
+            "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn drafts_future_and_unlisted_entries() {
+        let site = TestSite::new(
+            "published",
+            &[
+                (
+                    "templates/list.html",
+                    "{% for e in pages.blog %}{{ e.file_name }} {% endfor %}|\
+                        {% for e in tags.t.entries %}{{ e.file_name }} {% endfor %}",
+                ),
+                ("templates/entry.html", "{{ metadata.title }}"),
+                (
+                    "posts/a-published.md",
+                    entry("tags: [t]\ncreated_at: 2024-01-01", "").as_str(),
+                ),
+                (
+                    "posts/b-draft.md",
+                    entry("tags: [t]\ncreated_at: 2024-02-01\ndraft: true", "").as_str(),
+                ),
+                (
+                    "posts/c-future.md",
+                    entry("tags: [t]\ncreated_at: 2999-01-01", "").as_str(),
+                ),
+                (
+                    "posts/d-unlisted.md",
+                    entry("tags: [t]\ncreated_at: 2024-03-01\nunlisted: true", "").as_str(),
+                ),
+            ],
+        );
+        let pages = "search_index: true
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+";
+
+        site.build(site.config(pages));
+
+        assert_eq!(site.output("blog/index.html"), "a-published |a-published ");
+        assert!(site.dir.join("dist/blog/a-published/index.html").exists());
+        assert!(!site.dir.join("dist/blog/b-draft").exists());
+        assert!(!site.dir.join("dist/blog/c-future").exists());
+        assert!(site.dir.join("dist/blog/d-unlisted/index.html").exists());
+
+        let search_index = site.output("search-index.json");
+        assert!(search_index.contains("/blog/a-published/"));
+        assert!(!search_index.contains("d-unlisted"));
+
+        std::fs::remove_dir_all(site.dir.join("dist")).unwrap();
+        site.build(site.config(format!("drafts: true\nfuture: true\n{}", pages).as_str()));
+
+        assert_eq!(
+            site.output("blog/index.html"),
+            "a-published b-draft c-future |a-published b-draft c-future "
+        );
+        assert!(site.dir.join("dist/blog/b-draft/index.html").exists());
+        assert!(site.dir.join("dist/blog/c-future/index.html").exists());
+        assert!(site.dir.join("dist/blog/d-unlisted/index.html").exists());
+    }
+
+    #[test]
+    fn external_links_open_in_a_new_tab() {
+        let markdown = "[a](https://example.com) [b](/blog/post/) [c](mailto:me@example.com) \
+            [d](tel:+15550100) [e](//cdn.example.com/x)";
+
+        assert_eq!(
+            render(markdown, &config("external_links_new_tab: true")),
+            "<p><a href=\"https://example.com\" target=\"_blank\" \
+                rel=\"noopener noreferrer nofollow\">a</a> \
+                <a href=\"/blog/post/\">b</a> \
+                <a href=\"mailto:me@example.com\">c</a> \
+                <a href=\"tel:+15550100\">d</a> \
+                <a href=\"//cdn.example.com/x\" target=\"_blank\" \
+                rel=\"noopener noreferrer nofollow\">e</a></p>\n"
+        );
+        assert_eq!(
+            render(
+                "[a](https://example.com)",
+                &config("external_links_new_tab: true\nexternal_links_rel: noopener")
+            ),
+            "<p><a href=\"https://example.com\" target=\"_blank\" rel=\"noopener\">a</a></p>\n"
+        );
+        assert_eq!(
+            render("[a](https://example.com)", &config("")),
+            "<p><a href=\"https://example.com\">a</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn redirects_file_format() {
+        let site = TestSite::new(
+            "redirects",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", ""),
+                (
+                    "posts/first.md",
+                    entry("aliases: [old-first, older/first]", "").as_str(),
+                ),
+                (
+                    "posts/second.md",
+                    entry("aliases: [old-second]", "").as_str(),
+                ),
+            ],
+        );
+        let pages = "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+";
+
+        site.build(site.config(format!("redirect_style: netlify\n{}", pages).as_str()));
+
+        assert_eq!(
+            site.output("_redirects"),
+            "/blog/old-first/ /blog/first/ 301\n\
+                /blog/older/first/ /blog/first/ 301\n\
+                /blog/old-second/ /blog/second/ 301\n"
+        );
+        assert!(!site.dir.join("dist/blog/old-first").exists());
+
+        std::fs::remove_dir_all(site.dir.join("dist")).unwrap();
+        site.build(site.config(format!("redirect_style: both\n{}", pages).as_str()));
+
+        assert!(site.dir.join("dist/_redirects").exists());
+        assert!(site
+            .output("blog/old-first/index.html")
+            .contains("url=/blog/first/"));
+
+        std::fs::remove_dir_all(site.dir.join("dist")).unwrap();
+        site.build(site.config(pages));
+
+        assert!(!site.dir.join("dist/_redirects").exists());
+    }
+
+    #[test]
+    fn inline_and_display_math() {
+        let config = config("");
+
+        assert_eq!(
+            render(
+                "Euler: $e^{i\\pi} < 0$.\n\n$$\n\\sum_{n=1}^\\infty \\frac{1}{n^2}\n$$",
+                &config
+            ),
+            "<p>Euler: <span class=\"math inline\">e^{i\\pi} &lt; 0</span>.</p>\n\
+                <div class=\"math display\">\n\\sum_{n=1}^\\infty \\frac{1}{n^2}\n</div>\n"
+        );
+        assert_eq!(
+            render("Within $$x^2$$ text", &config),
+            "<p>Within <span class=\"math display\">x^2</span> text</p>\n"
+        );
+    }
+
+    #[test]
+    fn truncate_on_word_boundaries() {
+        assert_eq!(truncate("The quick brown fox", 12, false), "The quick…");
+        assert_eq!(truncate("The quick brown fox", 2, true), "The quick…");
+        assert_eq!(
+            truncate("The quick brown fox", 4, true),
+            "The quick brown fox"
+        );
+        assert_eq!(
+            truncate("The quick brown fox ", 19, false),
+            "The quick brown fox"
+        );
+    }
+
+    #[test]
+    fn truncate_on_char_boundaries() {
+        // A single word that's too long is cut within it
+        assert_eq!(truncate("Supercalifragilistic", 6, false), "Super…");
+        assert_eq!(truncate("你好世界你好世界", 5, false), "你好世界…");
+        assert_eq!(truncate("Ünïcödé wörds everywhere", 10, false), "Ünïcödé…");
+        assert_eq!(truncate("Ünïcödé", 7, false), "Ünïcödé");
+    }
+
+    #[test]
+    fn truncate_never_splits_entities() {
+        assert_eq!(truncate("a&amp;b&amp;c", 4, false), "a&amp;b…");
+        assert_eq!(truncate("a&amp;b&amp;c", 3, false), "a&amp;…");
+        assert_eq!(truncate("Fish &amp; chips &amp;", 7, false), "Fish &amp;…");
+        assert_eq!(truncate("Fish &#8230;", 6, false), "Fish &#8230;");
+    }
+
+    #[test]
+    fn truncate_filter() {
+        let handle = TemplateHandle::new(&config(""));
+        let render = |template: &str| handle.env.render_str(template, ()).unwrap();
+
+        assert_eq!(
+            render("{{ 'The quick brown fox'|truncate(12) }}"),
+            "The quick…"
+        );
+        assert_eq!(
+            render("{{ 'The quick brown fox'|truncate(2, words=true) }}"),
+            "The quick…"
+        );
+    }
+
+    #[cfg(feature = "images")]
+    #[test]
+    fn covers_smaller_than_every_width_have_no_srcset() {
+        let site = TestSite::new(
+            "small-cover",
+            &[
+                ("templates/list.html", ""),
+                (
+                    "templates/entry.html",
+                    "{{ metadata.cover_srcset is none }}",
+                ),
+                (
+                    "posts/a.md",
+                    entry("cover: { src: /tiny.png, alt: A }", "").as_str(),
+                ),
+                (
+                    "posts/b.md",
+                    entry("cover: { src: /tiny.png, alt: B }", "").as_str(),
+                ),
+            ],
+        );
+        image::RgbImage::new(1, 1)
+            .save(site.dir.join("tiny.png"))
+            .unwrap();
+        let config = site.config(
+            "images:
+  widths: [480]
+  source_dir: {dir}
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        // The second cover comes from the cache
+        assert_eq!(site.output("blog/a/index.html"), "true");
+        assert_eq!(site.output("blog/b/index.html"), "true");
+    }
+
+    #[test]
+    fn template_errors_name_the_markdown_file() {
+        let site = TestSite::new(
+            "render-error",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", "{% block content %}{% endblock %}"),
+                ("posts/broken.md", entry("", "{% if %}").as_str()),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        let error = format!("{:#}", build_from_dir(config).unwrap_err());
+
+        assert!(error.contains("posts/broken.md"), "{}", error);
+    }
+
+    #[test]
+    fn normalize_html_is_stable() {
+        let inputs = [
+            "",
+            "\n\n\n",
+            "<p>a</p>   \n\n\n\n<p>b</p>\t\n",
+            "\n\n<html>\n  <body>  \n\n\n  </body>\n</html>",
+            "<pre><code>keep   \n\n\n   this\n</code></pre>\n\n\n<p>after</p>  ",
+            "<pre>unclosed  \n\n\n",
+            "<p>x</p><pre>a  \n\n</pre><p>y</p>  \n\n",
+        ];
+
+        for input in inputs {
+            let normalized = normalize_html(input);
+
+            assert_eq!(
+                normalize_html(normalized.as_str()),
+                normalized,
+                "{:?}",
+                input
+            );
+            assert_eq!(normalize_html(input), normalized, "{:?}", input);
+        }
+
+        assert_eq!(
+            normalize_html("<p>a</p>   \n\n\n\n<p>b</p>\t\n\n"),
+            "<p>a</p>\n\n<p>b</p>\n"
+        );
+        assert_eq!(
+            normalize_html("<pre>keep   \n\n\n   this</pre>\n"),
+            "<pre>keep   \n\n\n   this</pre>\n"
+        );
+    }
+
+    #[test]
+    fn normalized_builds_are_the_same_every_time() {
+        let site = TestSite::new(
+            "normalized",
+            &[
+                ("templates/list.html", "<ul>   \n\n\n{% for e in pages.blog %}  <li>{{ e.title }}</li>  \n{% endfor %}</ul>\n\n"),
+                ("templates/entry.html", "{% block content %}{% endblock %}   \n\n\n"),
+                ("posts/a.md", entry("title: A", "Text   \n\n\n```\ncode   \n\n\n```").as_str()),
+                ("posts/b.md", entry("title: B", "More").as_str()),
+            ],
+        );
+        let config = site.config(
+            "normalize_html: true
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config.clone());
+        let first = site.outputs();
+
+        std::fs::remove_dir_all(site.dir.join("dist")).unwrap();
+        site.build(config);
+
+        assert_eq!(first, site.outputs());
+
+        for contents in first.values() {
+            let html = std::str::from_utf8(contents).unwrap();
+            assert_eq!(normalize_html(html), html);
+        }
+    }
+
+    #[test]
+    fn template_tags_end_before_the_next_blank_line() {
+        let markdown = "## Intro {#intro}\n\nSome text\n\n{# note #}\n\nMore";
+
+        assert_eq!(
+            render(markdown, &config("")),
+            "<h2 id=\"intro\">Intro</h2>\n<p>Some text</p>\n<p>{# note #}</p>\n<p>More</p>\n"
+        );
+
+        let handle = TemplateHandle::new(&config(""));
+        let html = handle
+            .env
+            .render_str(render(markdown, &config("")).as_str(), ())
+            .unwrap();
+
+        assert_eq!(
+            html,
+            "<h2 id=\"intro\">Intro</h2>\n<p>Some text</p>\n<p></p>\n<p>More</p>"
+        );
+    }
+
+    #[test]
+    fn template_tags_can_span_lines_of_a_paragraph() {
+        assert_eq!(
+            render("{# a comment\nover two lines #} *text*", &config("")),
+            "<p>{# a comment\nover two lines #} <em>text</em></p>\n"
+        );
+    }
+
+    #[test]
+    fn template_tags_in_code_are_left_alone() {
+        assert_eq!(
+            render("`{#` and {{ x }}\n\n```\n{% raw %}{{ x }}{% endraw %}\n```", &config("")),
+            "<p><code>{#</code> and {{ x }}</p>\n<pre><code>{% raw %}{{ x }}{% endraw %}\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn entries_include_templates_from_templates_dir() {
+        let site = TestSite::new(
+            "include",
+            &[
+                ("templates/list.html", ""),
+                (
+                    "templates/layout/entry.html",
+                    "<main>{% block content %}{% endblock %}</main>",
+                ),
+                (
+                    "templates/partials/bio.html",
+                    "<aside>Bio of {{ metadata.title }}</aside>",
+                ),
+                (
+                    "posts/post.md",
+                    entry("title: Post", "Text\n\n{% include \"partials/bio.html\" %}").as_str(),
+                ),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: layout/entry.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(
+            site.output("blog/post/index.html"),
+            "<main>\n<p>Text</p>\n<p><aside>Bio of Post</aside></p>\n\n</main>"
+        );
+    }
+
+    #[test]
+    fn headings_json_leaves_template_tags_out() {
+        let site = TestSite::new(
+            "headings-json",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", "{{ headings_json }}"),
+                (
+                    "posts/post.md",
+                    entry(
+                        "title: Post",
+                        "## Hi {{ metadata.title }}\n\n### a < b {% if true %}c{% endif %}",
+                    )
+                    .as_str(),
+                ),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(
+            site.output("blog/post/index.html"),
+            "[{\"level\":2,\"slug\":\"hi\",\"text\":\"Hi\"},\
+                {\"level\":3,\"slug\":\"a-b-c\",\"text\":\"a \\u003c b c\"}]"
+        );
+    }
+
+    #[test]
+    fn covers_render_as_their_url() {
+        let site = TestSite::new(
+            "cover-url",
+            &[
+                ("templates/list.html", ""),
+                (
+                    "templates/entry.html",
+                    "{{ metadata.cover|safe }}|{{ metadata.cover.src|safe }}|{{ metadata.cover.alt }}",
+                ),
+                ("posts/a.md", entry("cover: /a.png", "").as_str()),
+                (
+                    "posts/b.md",
+                    entry("cover: { src: /b.png, alt: B }", "").as_str(),
+                ),
+            ],
+        );
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        site.build(config);
+
+        assert_eq!(site.output("blog/a/index.html"), "/a.png|/a.png|none");
+        assert_eq!(site.output("blog/b/index.html"), "/b.png|/b.png|B");
+    }
+
+    #[test]
+    fn entries_get_only_the_assets_they_link_to() {
+        let site = TestSite::new(
+            "copy-assets",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", ""),
+                (
+                    "posts/a.md",
+                    entry(
+                        "cover: ./cover.png",
+                        "![Diagram](img/diagram.png)\n\n[Notes](./notes.txt?v=2)",
+                    )
+                    .as_str(),
+                ),
+                (
+                    "posts/b.html",
+                    entry("", "<img alt=\"\" src=\"img/diagram.png\">").as_str(),
+                ),
+                ("posts/c.md", entry("", "[Elsewhere](/notes.txt)").as_str()),
+                ("posts/cover.png", "cover"),
+                ("posts/notes.txt", "notes"),
+                ("posts/img/diagram.png", "diagram"),
+                ("posts/unused.txt", "unused"),
+            ],
+        );
+        let config = site.config(
+            "html_extensions: [html]
+pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+        copy_assets: true
+",
+        );
+
+        site.build(config);
+
+        let assets: Vec<PathBuf> = site
+            .outputs()
+            .into_keys()
+            .filter(|path| !path.ends_with("index.html"))
+            .collect();
+
+        assert_eq!(
+            assets,
+            [
+                "blog/a/cover.png",
+                "blog/a/img/diagram.png",
+                "blog/a/notes.txt",
+                "blog/b/img/diagram.png",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn relative_links_of_entries_outside_the_cwd_are_not_local_files() {
+        let site = TestSite::new(
+            "local-files",
+            &[
+                ("templates/list.html", ""),
+                ("templates/entry.html", ""),
+                (
+                    "posts/a.md",
+                    entry(
+                        "",
+                        "![Diagram](img/diagram.png) [B](./b.md) [Home](../../../../../../home/me/notes.txt)",
+                    )
+                    .as_str(),
+                ),
+                ("posts/b.md", entry("", "").as_str()),
+            ],
+        );
+        assert!(!site.dir.starts_with(std::env::current_dir().unwrap()));
+        let config = site.config(
+            "pages:
+  - name: blog
+    template_path: list.html
+    entries:
+      - source_dir: {dir}/posts
+        template_path: entry.html
+",
+        );
+
+        let local_files: Vec<String> = site
+            .build(config)
+            .warnings
+            .into_iter()
+            .filter_map(|warning| match warning {
+                BuildWarning::LocalFile(_, url) => Some(url),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(local_files, ["../../../../../../home/me/notes.txt"]);
+    }
+}
@@ -1,4 +1,6 @@
 use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
 
 use axum::Router;
 use puggle_lib::Config;
@@ -10,15 +12,92 @@ use tower_http::services::ServeDir;
 pub enum ServerError {
     #[error("failed to bind tcp listener to port. reason: {0}")]
     TcpListener(#[from] std::io::Error),
+    #[cfg(feature = "tls")]
+    #[error("failed to load tls certificate {0:?} and key {1:?}. reason: {2}")]
+    Tls(PathBuf, PathBuf, std::io::Error),
+}
+
+/// Certificate and private key, both PEM encoded, to serve over HTTPS with
+#[cfg(feature = "tls")]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[cfg(feature = "tls")]
+impl TlsFiles {
+    /// Reads and parses both files, so that a bad certificate or key is
+    /// reported before the server starts.
+    pub async fn load(&self) -> Result<axum_server::tls_rustls::RustlsConfig, ServerError> {
+        axum_server::tls_rustls::RustlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .map_err(|e| ServerError::Tls(self.cert.clone(), self.key.clone(), e))
+    }
+}
+
+/// Router that serves the files of the config's `dest_dir`. It can be
+/// nested in an app of your own, next to its other routes.
+pub fn router(config: &Config) -> Router {
+    Router::new().nest_service("/", ServeDir::new(config.dest_dir.as_path()))
+}
+
+/// A server bound to its port, but not serving anything yet
+pub struct Server {
+    listener: TcpListener,
+    app: Router,
+}
+
+impl Server {
+    /// Binds to the config's `port`. With port 0, the OS picks a free one,
+    /// which `local_addr` tells.
+    pub async fn bind(config: Config) -> Result<Self, ServerError> {
+        let app = router(&config);
+
+        let local_address = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let listener = TcpListener::bind(local_address).await?;
+
+        Ok(Self { listener, app })
+    }
+
+    /// Address the server is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr, ServerError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn serve(self) -> Result<(), ServerError> {
+        axum::serve(self.listener, self.app).await?;
+        Ok(())
+    }
+
+    /// Serves over HTTPS, with a config from `TlsFiles::load`
+    #[cfg(feature = "tls")]
+    pub async fn serve_tls(
+        self,
+        tls: axum_server::tls_rustls::RustlsConfig,
+    ) -> Result<(), ServerError> {
+        axum_server::from_tcp_rustls(self.listener.into_std()?, tls)
+            .serve(self.app.into_make_service())
+            .await?;
+        Ok(())
+    }
 }
 
 pub async fn run(config: Config) -> Result<(), ServerError> {
-    let app = Router::new().nest_service("/", ServeDir::new(config.dest_dir));
+    let server = Server::bind(config).await?;
+
+    println!("serving on http://{}", server.local_addr()?);
+
+    server.serve().await
+}
+
+/// Like `run`, but over HTTPS. The certificate and key are loaded before
+/// binding, so a bad pair fails without ever serving.
+#[cfg(feature = "tls")]
+pub async fn run_tls(config: Config, files: TlsFiles) -> Result<(), ServerError> {
+    let tls = files.load().await?;
+    let server = Server::bind(config).await?;
 
-    let local_address = SocketAddr::from(([0, 0, 0, 0], 3000));
-    let listener = TcpListener::bind(local_address).await?;
-    let _local_address = listener.local_addr()?;
+    println!("serving on https://{}", server.local_addr()?);
 
-    axum::serve(listener, app).await?;
-    Ok(())
+    server.serve_tls(tls).await
 }